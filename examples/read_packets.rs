@@ -11,27 +11,24 @@ async fn main() {
     let stream = config.connect().await.unwrap();
     // Create a data reader
     let mut data_reader = DataReader::new(stream);
-    // Poll data packets asynchronously
+    // Poll data packets asynchronously, converted into specific variants
     let timer = Instant::now();
-    while let Ok(packet) = data_reader.poll_next().await {
-        // Optionally parse the packet into a specific variant
-        // and handle it accordingly
-        match packet.try_into() {
-            Ok(PacketVariant::RawWave { .. }) => {}
-            Ok(PacketVariant::EegPower {
+    while let Ok(variant) = data_reader.poll_next_variant().await {
+        // Handle each variant accordingly
+        match variant {
+            PacketVariant::RawWave { .. }
+            | PacketVariant::Blink { .. }
+            | PacketVariant::RelativePower { .. } => {}
+            PacketVariant::EegPower {
                 poor_signal,
                 eeg_power,
                 ..
-            }) => {
+            } => {
                 println!(
-                    "[{:.02?}s]: {poor_signal:?} | {eeg_power:?}",
+                    "[{:.02?}s]: {poor_signal:?} | {eeg_power}",
                     timer.elapsed().as_secs_f64()
                 );
             }
-            Err(e) => {
-                eprintln!("Error parsing packet: {:?}", e);
-                continue;
-            }
         }
     }
 }
@@ -0,0 +1,164 @@
+//! Provides a packet reader for synchronous code. It defines the
+//! `BlockingDataReader` struct which owns a single-threaded tokio runtime to
+//! drive the connection, so a synchronous `main` can read packets with a
+//! plain `for` loop, without setting up a runtime.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{blocking::BlockingDataReader, device::DeviceConfig};
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let reader = BlockingDataReader::connect(&DeviceConfig::default())?;
+//!     for packet in reader {
+//!         println!("{:?}", packet?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Runtimes
+//!
+//! The methods of `BlockingDataReader` block the calling thread while the
+//! runtime it owns drives the connection. They panic when called from
+//! within an asynchronous context, e.g. a task spawned on another tokio
+//! runtime, where `DataReader` should be used directly instead.
+
+use std::io::Error;
+
+use bluer::rfcomm;
+use futures::StreamExt;
+use tokio::{
+    io::AsyncRead,
+    runtime::{Builder, Runtime},
+};
+
+use crate::{
+    comm::{CommError, DataReader, Packet},
+    device::{self, DeviceConfig},
+};
+
+/// Represents a data reader that blocks the calling thread until a packet is
+/// received, for use from synchronous code.
+pub struct BlockingDataReader<R = rfcomm::Stream> {
+    /// Runtime driving the stream
+    runtime: Runtime,
+    /// The wrapped reader
+    reader: DataReader<R>,
+}
+
+impl BlockingDataReader {
+    /// Connects to the device with a runtime owned by the reader, see
+    /// `DeviceConfig::connect`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to connect with.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BlockingDataReader, device::Error>` - The connected reader.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the runtime cannot be built, or
+    /// if the connection fails.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if called from within an asynchronous context.
+    pub fn connect(config: &DeviceConfig) -> device::Result<BlockingDataReader> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let stream = runtime.block_on(config.connect())?;
+        Ok(BlockingDataReader {
+            runtime,
+            reader: DataReader::new(stream),
+        })
+    }
+}
+
+impl<R: AsyncRead + Unpin> BlockingDataReader<R> {
+    /// Wraps a reader whose stream does not depend on another runtime, e.g.
+    /// one reading recorded bytes. Streams created within another runtime,
+    /// such as RFCOMM streams, stop working once that runtime is dropped;
+    /// use `connect` for them.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader to wrap.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the runtime cannot be built.
+    pub fn new(reader: DataReader<R>) -> Result<BlockingDataReader<R>, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(BlockingDataReader { runtime, reader })
+    }
+
+    /// Applies options to the wrapped reader, e.g. a warmup period.
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - The closure, given the reader and returning the
+    ///   configured reader.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_reader(mut self, configure: impl FnOnce(DataReader<R>) -> DataReader<R>) -> Self {
+        self.reader = configure(self.reader);
+        self
+    }
+
+    /// Returns the wrapped reader, e.g. to look at its snapshot.
+    pub fn reader(&self) -> &DataReader<R> {
+        &self.reader
+    }
+
+    /// Reads the next data packet, blocking until one is received, see
+    /// `DataReader::poll_next`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from the stream fails,
+    /// or if the stream ends.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if called from within an asynchronous context.
+    pub fn read(&mut self) -> Result<Packet, CommError> {
+        self.runtime.block_on(self.reader.poll_next())
+    }
+
+    /// Consumes the reader, returning the wrapped reader and the runtime
+    /// driving its stream.
+    pub fn into_inner(self) -> (DataReader<R>, Runtime) {
+        (self.reader, self.runtime)
+    }
+}
+
+impl<R: AsyncRead + Unpin> Iterator for BlockingDataReader<R> {
+    type Item = Result<Packet, CommError>;
+
+    /// Reads the next packet, blocking until one is received. Follows the
+    /// same contract as the `Stream` implementation of `DataReader`.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(StreamExt::next(&mut self.reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::frame;
+
+    #[test]
+    fn test_blocking_reader() {
+        let bytes = frame(&[0x04, 0x32]).repeat(2);
+
+        let mut reader = BlockingDataReader::new(DataReader::new(&bytes[..])).unwrap();
+        assert_eq!(reader.read().unwrap().attention, Some(50));
+        let rest: Vec<_> = reader.map(|p| p.unwrap().attention).collect();
+        assert_eq!(rest, vec![Some(50)]);
+    }
+}
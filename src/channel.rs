@@ -0,0 +1,464 @@
+//! Provides a bounded channel to hand packets read by a background task over
+//! to a consumer. It defines the `OverflowPolicy` enum which decides what
+//! happens when the consumer falls behind, and the `Receiver` struct which
+//! yields the queued items. The `Subscriptions` struct adds any number of
+//! consumers to a single background task, each with its own channel.
+//!
+//! # Choosing a policy
+//!
+//! Raw wave packets arrive at 512Hz, so a consumer that stalls for a second
+//! falls 512 packets behind.
+//!
+//! - `DropOldest` keeps the queue fresh by discarding the oldest packets. Use
+//!   it for real-time visualization, where only the latest data matters.
+//! - `DropNewest` keeps the queued packets and discards incoming ones. Use it
+//!   when a contiguous backlog is more useful than the latest data.
+//! - `Block` stops reading from the device until there is room. No packet is
+//!   lost in the channel, but the device is throttled once the socket buffer
+//!   fills up. Use it for lossless recording with a consumer that keeps up on
+//!   average.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{channel::OverflowPolicy, comm::DataReader, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let stream = DeviceConfig::default().connect().await.unwrap();
+//!     let mut packets = DataReader::new(stream).spawn(1024, OverflowPolicy::DropOldest);
+//!
+//!     while let Some(packet) = packets.recv().await {
+//!         println!("{:?}", packet);
+//!     }
+//! }
+//! ```
+//!
+//! # Prefetching
+//!
+//! With `OverflowPolicy::Block`, the channel is a prefetch buffer: the
+//! background task keeps reading and parsing at the pace of the device while
+//! the consumer stalls, up to the capacity of the channel, before the device
+//! is throttled. The receiver of packets has the same `poll_next` method as
+//! `DataReader`, so a consumer loop switches over without changes:
+//!
+//! ```rust,no_run
+//! use cerebrust::{channel::OverflowPolicy, comm::DataReader, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream).spawn(2048, OverflowPolicy::Block);
+//!     loop {
+//!         let packet = reader.poll_next().await?;
+//!         println!("{:?} ({} prefetched)", packet, reader.len());
+//!     }
+//! }
+//! ```
+//!
+//! # Multiple consumers
+//!
+//! The device accepts a single connection, so consumers that need the same
+//! packets share one background task with `DataReader::spawn_shared`:
+//!
+//! ```rust,no_run
+//! use cerebrust::{channel::OverflowPolicy, comm::DataReader, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let stream = DeviceConfig::default().connect().await.unwrap();
+//!     let subscriptions = DataReader::new(stream).spawn_shared();
+//!     let mut raw = subscriptions.subscribe(1024, OverflowPolicy::DropOldest);
+//!     let mut power = subscriptions.subscribe(16, OverflowPolicy::Block);
+//!     // Hand each receiver over to its own consumer
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    io::{Error, ErrorKind},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures::{Stream, task::AtomicWaker};
+use tokio::task::AbortHandle;
+
+use crate::comm::Packet;
+
+/// Represents what a bounded channel does with a new item when it is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued item to make room for the new one
+    #[default]
+    DropOldest,
+    /// Discard the new item
+    DropNewest,
+    /// Wait until the consumer makes room
+    Block,
+}
+
+/// Queue state shared by both ends of the channel.
+struct State<T> {
+    /// Queued items, oldest first
+    queue: VecDeque<T>,
+    /// Number of items discarded due to overflow
+    dropped: u64,
+    /// Whether the sender has been dropped
+    sender_closed: bool,
+    /// Whether the receiver has been dropped
+    receiver_closed: bool,
+}
+
+/// Data shared by both ends of the channel.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Waker of the receiver waiting for an item
+    receiver_waker: AtomicWaker,
+    /// Waker of the sender waiting for room
+    sender_waker: AtomicWaker,
+}
+
+/// Creates a bounded channel holding up to `capacity` items.
+///
+/// # Panics
+///
+/// This function panics if `capacity` is zero.
+pub(crate) fn channel<T>(capacity: usize, policy: OverflowPolicy) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "Channel capacity must be non-zero");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            dropped: 0,
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        capacity,
+        policy,
+        receiver_waker: AtomicWaker::new(),
+        sender_waker: AtomicWaker::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, task: None },
+    )
+}
+
+/// Sending end of a bounded channel.
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends an item, applying the overflow policy if the channel is full.
+    /// Returns the item back if the receiver has been dropped.
+    pub(crate) async fn send(&self, item: T) -> Result<(), T> {
+        let mut item = Some(item);
+        poll_fn(|cx| {
+            // Register before checking, so a concurrent receive wakes us up
+            self.shared.sender_waker.register(cx.waker());
+            let mut state = self.shared.state.lock().unwrap();
+            if state.receiver_closed {
+                return Poll::Ready(Err(item.take().unwrap()));
+            }
+            if state.queue.len() >= self.shared.capacity {
+                match self.shared.policy {
+                    OverflowPolicy::DropOldest => {
+                        state.queue.pop_front();
+                        state.dropped += 1;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        state.dropped += 1;
+                        return Poll::Ready(Ok(()));
+                    }
+                    OverflowPolicy::Block => return Poll::Pending,
+                }
+            }
+            state.queue.push_back(item.take().unwrap());
+            drop(state);
+            self.shared.receiver_waker.wake();
+            Poll::Ready(Ok(()))
+        })
+        .await
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().sender_closed = true;
+        self.shared.receiver_waker.wake();
+    }
+}
+
+/// Represents the receiving end of a bounded channel fed by a background
+/// task. The channel ends once the task stops and all queued items have been
+/// received. If the receiver owns the task, dropping it stops the task right
+/// away, releasing everything the task holds, e.g. the device connection.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Background task feeding the channel, if owned by this receiver
+    task: Option<AbortHandle>,
+}
+
+impl<T> Receiver<T> {
+    /// Makes the receiver own the background task feeding the channel.
+    pub(crate) fn set_task(&mut self, task: AbortHandle) {
+        self.task = Some(task);
+    }
+
+    /// Stops the background task feeding the channel, if owned by this
+    /// receiver, without waiting for its next item. Items already queued can
+    /// still be received, after which the channel ends.
+    pub fn abort(&self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+
+    /// Receives the next item, waiting until one is available. Returns `None`
+    /// once the channel has ended.
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Polls the next item, registering the current task to be woken up when
+    /// one is available.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // Register before checking, so a concurrent send wakes us up
+        self.shared.receiver_waker.register(cx.waker());
+        let mut state = self.shared.state.lock().unwrap();
+        match state.queue.pop_front() {
+            Some(item) => {
+                drop(state);
+                self.shared.sender_waker.wake();
+                Poll::Ready(Some(item))
+            }
+            None if state.sender_closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Returns the number of items currently queued.
+    pub fn len(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    /// Whether no item is currently queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of items the channel queues.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of items discarded so far due to overflow.
+    pub fn dropped(&self) -> u64 {
+        self.shared.state.lock().unwrap().dropped
+    }
+}
+
+impl Receiver<Result<Packet, Error>> {
+    /// Receives the next packet read by the background task, waiting until
+    /// one is available, like `DataReader::poll_next`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the read error of the background task, or
+    /// an `UnexpectedEof` error once the channel has ended.
+    pub async fn poll_next(&mut self) -> Result<Packet, Error> {
+        match self.recv().await {
+            Some(result) => result,
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "Stream has ended")),
+        }
+    }
+}
+
+/// Represents a handle to add consumers to a background task shared by
+/// several consumers. Each consumer gets its own channel, so a slow consumer
+/// only loses its own items, except with `OverflowPolicy::Block`, which
+/// stalls the task for every consumer. Consumers only receive items read
+/// after they subscribed.
+pub struct Subscriptions<T> {
+    /// Senders of new consumers, not yet picked up by the task
+    pending: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T> Clone for Subscriptions<T> {
+    fn clone(&self) -> Self {
+        Subscriptions {
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<T> Subscriptions<T> {
+    /// Adds a consumer with its own bounded channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of items queued. Must be non-zero.
+    /// * `policy` - What to do with new items when the channel is full.
+    ///
+    /// # Returns
+    ///
+    /// * `Receiver` - The receiving end of the consumer's channel. It ends
+    ///   once the background task stops.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is zero.
+    pub fn subscribe(&self, capacity: usize, policy: OverflowPolicy) -> Receiver<T> {
+        let (sender, receiver) = channel(capacity, policy);
+        self.pending.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// Sending side of `Subscriptions`, owned by the background task.
+pub(crate) struct Fanout<T> {
+    /// Senders of new consumers, shared with the `Subscriptions` handles
+    pending: Arc<Mutex<Vec<Sender<T>>>>,
+    /// Senders of the current consumers
+    senders: Vec<Sender<T>>,
+}
+
+/// Creates a fan-out to any number of bounded channels, and the handle to add
+/// consumers to it.
+pub(crate) fn fanout<T>() -> (Fanout<T>, Subscriptions<T>) {
+    let pending = Arc::new(Mutex::new(Vec::new()));
+    (
+        Fanout {
+            pending: pending.clone(),
+            senders: Vec::new(),
+        },
+        Subscriptions { pending },
+    )
+}
+
+impl<T> Fanout<T> {
+    /// Sends a copy of an item to every current consumer, made by `make`.
+    /// Consumers whose receiver has been dropped are removed. Returns
+    /// `false` once there is no consumer left and no handle to add one.
+    pub(crate) async fn send_with(&mut self, make: impl Fn() -> T) -> bool {
+        self.senders.append(&mut self.pending.lock().unwrap());
+        let mut i = 0;
+        while i < self.senders.len() {
+            if self.senders[i].send(make()).await.is_err() {
+                self.senders.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        !self.senders.is_empty() || Arc::strong_count(&self.pending) > 1
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.abort();
+        self.shared.state.lock().unwrap().receiver_closed = true;
+        self.shared.sender_waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_overflow_policies() {
+        let (sender, mut receiver) = channel(2, OverflowPolicy::DropOldest);
+        for i in 0..3 {
+            sender.send(i).await.unwrap();
+        }
+        drop(sender);
+        assert_eq!(receiver.dropped(), 1);
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, Some(2));
+        assert_eq!(receiver.recv().await, None);
+
+        let (sender, mut receiver) = channel(2, OverflowPolicy::DropNewest);
+        for i in 0..3 {
+            sender.send(i).await.unwrap();
+        }
+        drop(sender);
+        assert_eq!(receiver.dropped(), 1);
+        assert_eq!(receiver.recv().await, Some(0));
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, None);
+
+        let (sender, mut receiver) = channel(1, OverflowPolicy::Block);
+        let task = tokio::spawn(async move {
+            for i in 0..3 {
+                sender.send(i).await.unwrap();
+            }
+        });
+        for i in 0..3 {
+            assert_eq!(receiver.recv().await, Some(i));
+        }
+        task.await.unwrap();
+        assert_eq!(receiver.dropped(), 0);
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let (sender, mut receiver) = channel(4, OverflowPolicy::Block);
+        sender.send(Ok(Packet::default())).await.unwrap();
+        drop(sender);
+        assert_eq!(receiver.poll_next().await.unwrap(), Packet::default());
+        let e = receiver.poll_next().await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let (sender, mut receiver) = channel(1, OverflowPolicy::Block);
+        let task = tokio::spawn(async move {
+            sender.send(0).await.unwrap();
+            // Never completes, as the channel stays full
+            sender.send(1).await.unwrap();
+        });
+        receiver.set_task(task.abort_handle());
+        tokio::task::yield_now().await;
+        receiver.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(receiver.recv().await, Some(0));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_fanout() {
+        let (mut fanout, subscriptions) = fanout();
+        let mut first = subscriptions.subscribe(4, OverflowPolicy::DropOldest);
+        assert!(fanout.send_with(|| 0).await);
+        let second = subscriptions.subscribe(1, OverflowPolicy::DropNewest);
+        assert!(fanout.send_with(|| 1).await);
+        assert!(fanout.send_with(|| 2).await);
+        assert_eq!(second.dropped(), 1);
+        drop(second);
+        drop(subscriptions);
+        assert!(fanout.send_with(|| 3).await);
+        for i in 0..4 {
+            assert_eq!(first.recv().await, Some(i));
+        }
+        drop(first);
+        assert!(!fanout.send_with(|| 4).await);
+    }
+}
@@ -0,0 +1,125 @@
+//! Provides the integration point for classifiers of mental states. It
+//! defines the `StateLabel` enum which a classifier assigns to packets, the
+//! `Classifier` type which `DataReader::with_classifier` runs on each packet,
+//! and the `ThresholdClassifier` struct, a trivial classifier based on the
+//! eSense values.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{classify::ThresholdClassifier, comm::DataReader, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut classifier = ThresholdClassifier::new(60, 60);
+//!     let mut reader =
+//!         DataReader::new(stream).with_classifier(Box::new(move |p| classifier.classify(p)));
+//!     loop {
+//!         let packet = reader.poll_next().await?;
+//!         if let Some(state) = packet.state {
+//!             println!("{:?}", state);
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::comm::Packet;
+
+/// Represents the mental state assigned to a packet by a classifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StateLabel {
+    /// Concentrating, e.g. high attention
+    Focused,
+    /// Calm, e.g. high meditation
+    Relaxed,
+    /// Neither focused nor relaxed
+    Neutral,
+    /// A state defined by the classifier, identified by its index
+    Other(u8),
+}
+
+/// Closure run on each packet, returning the state it recognizes, if any.
+pub type Classifier = Box<dyn FnMut(&Packet) -> Option<StateLabel> + Send>;
+
+/// Represents a classifier comparing the eSense values to fixed thresholds.
+#[derive(Debug, Clone, Copy)]
+pub struct ThresholdClassifier {
+    /// Attention from which a packet is `Focused`
+    attention: u8,
+    /// Meditation from which a packet is `Relaxed`
+    meditation: u8,
+}
+
+impl ThresholdClassifier {
+    /// # Arguments
+    ///
+    /// * `attention` - The attention from which a packet is `Focused` (0 ~ 100).
+    /// * `meditation` - The meditation from which a packet is `Relaxed` (0 ~ 100).
+    pub fn new(attention: u8, meditation: u8) -> ThresholdClassifier {
+        ThresholdClassifier {
+            attention,
+            meditation,
+        }
+    }
+
+    /// Classifies a packet. When both thresholds are reached, the eSense
+    /// value furthest above its threshold wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to classify.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<StateLabel>` - The state, `None` if the packet has no
+    ///   eSense value.
+    pub fn classify(&mut self, packet: &Packet) -> Option<StateLabel> {
+        let margin = |value: Option<u8>, threshold: u8| {
+            value.filter(|&x| x >= threshold).map(|x| x - threshold)
+        };
+        match (
+            margin(packet.attention, self.attention),
+            margin(packet.meditation, self.meditation),
+        ) {
+            _ if packet.attention.is_none() && packet.meditation.is_none() => None,
+            (Some(a), Some(m)) if m > a => Some(StateLabel::Relaxed),
+            (Some(_), _) => Some(StateLabel::Focused),
+            (None, Some(_)) => Some(StateLabel::Relaxed),
+            (None, None) => Some(StateLabel::Neutral),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_classifier() {
+        let mut classifier = ThresholdClassifier::new(60, 50);
+        let packet = |attention, meditation| Packet {
+            attention,
+            meditation,
+            ..Default::default()
+        };
+        assert_eq!(classifier.classify(&packet(None, None)), None);
+        assert_eq!(
+            classifier.classify(&packet(Some(70), Some(40))),
+            Some(StateLabel::Focused)
+        );
+        assert_eq!(
+            classifier.classify(&packet(Some(70), Some(80))),
+            Some(StateLabel::Relaxed)
+        );
+        assert_eq!(
+            classifier.classify(&packet(None, Some(50))),
+            Some(StateLabel::Relaxed)
+        );
+        assert_eq!(
+            classifier.classify(&packet(Some(30), Some(30))),
+            Some(StateLabel::Neutral)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Provides an adapter to reassemble readings that some firmware splits
+//! across adjacent packets. It defines the `Coalesce` struct which merges a
+//! raw wave packet immediately followed by an eSense or EEG power packet into
+//! a single `Packet`, giving a per-second view that aligns the raw context
+//! with the power summary.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use cerebrust::{coalesce::Coalesce, comm::DataReader, device::DeviceConfig};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut packets = Coalesce::new(DataReader::new(stream), Duration::from_millis(5));
+//!     while let Some(packet) = packets.next().await {
+//!         let packet = packet?;
+//!         if let (Some(raw_wave), Some(eeg_power)) = (packet.raw_wave, packet.eeg_power) {
+//!             println!("{raw_wave} | {eeg_power:?}");
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Latency
+//!
+//! A raw wave packet is held back until the next packet arrives, or until
+//! the time window expires, whichever comes first. Packets are never
+//! dropped or reordered. The adapter follows the same contract as the
+//! `Stream` implementation of `DataReader`, and requires a tokio runtime for
+//! its timer.
+
+use std::{
+    future::Future,
+    io::Error,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::Stream;
+use tokio::time::{Instant, Sleep, sleep};
+
+use crate::comm::Packet;
+
+/// Whether the packet holds a raw wave value and nothing else, apart from
+/// its timestamps and payload.
+fn is_raw_only(packet: &Packet) -> bool {
+    packet.raw_wave.is_some()
+        && Packet {
+            raw_wave: None,
+            received_at: None,
+            received_time: None,
+            raw: None,
+            ..packet.clone()
+        } == Packet::default()
+}
+
+/// Whether the packet holds eSense values or EEG power, but no raw wave value.
+fn is_summary(packet: &Packet) -> bool {
+    packet.raw_wave.is_none()
+        && (packet.poor_signal.is_some()
+            || packet.attention.is_some()
+            || packet.meditation.is_some()
+            || packet.eeg_power.is_some())
+}
+
+/// Represents an adapter that merges a raw wave packet with the eSense or EEG
+/// power packet immediately following it within a time window.
+pub struct Coalesce<S> {
+    /// The packet stream to reassemble
+    stream: S,
+    /// Maximum time between the two packets of a merged reading
+    window: Duration,
+    /// Raw wave packet held back, waiting for a packet to merge with
+    pending: Option<Packet>,
+    /// Expiry of the window of the pending packet
+    deadline: Pin<Box<Sleep>>,
+    /// Item to yield right after the pending packet
+    queued: Option<Result<Packet, Error>>,
+    /// Whether the wrapped stream has ended
+    ended: bool,
+}
+
+impl<S> Coalesce<S> {
+    pub fn new(stream: S, window: Duration) -> Coalesce<S> {
+        Coalesce {
+            stream,
+            window,
+            pending: None,
+            deadline: Box::pin(sleep(window)),
+            queued: None,
+            ended: false,
+        }
+    }
+
+    /// Consumes the adapter, returning the wrapped packet stream. A packet
+    /// held back is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Holds a raw wave packet back and restarts the time window.
+    fn hold(&mut self, packet: Packet) {
+        self.pending = Some(packet);
+        self.deadline.as_mut().reset(Instant::now() + self.window);
+    }
+}
+
+impl<S, E> Stream for Coalesce<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Packet, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(item) = this.queued.take() {
+            return Poll::Ready(Some(item));
+        }
+        while !this.ended {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(packet))) => {
+                    let Some(raw) = this.pending.take() else {
+                        if is_raw_only(&packet) {
+                            this.hold(packet);
+                            continue;
+                        }
+                        return Poll::Ready(Some(Ok(packet)));
+                    };
+                    if is_summary(&packet) && !this.deadline.is_elapsed() {
+                        return Poll::Ready(Some(Ok(Packet {
+                            raw_wave: raw.raw_wave,
+                            ..packet
+                        })));
+                    }
+                    if is_raw_only(&packet) {
+                        this.hold(packet);
+                    } else {
+                        this.queued = Some(Ok(packet));
+                    }
+                    return Poll::Ready(Some(Ok(raw)));
+                }
+                Poll::Ready(Some(Err(e))) => match this.pending.take() {
+                    Some(raw) => {
+                        this.queued = Some(Err(e.into()));
+                        return Poll::Ready(Some(Ok(raw)));
+                    }
+                    None => return Poll::Ready(Some(Err(e.into()))),
+                },
+                Poll::Ready(None) => this.ended = true,
+                Poll::Pending => {
+                    if this.pending.is_some() && this.deadline.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(this.pending.take().map(Ok));
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Ready(this.pending.take().map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, stream};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let raw = |raw_wave| Packet {
+            raw_wave: Some(raw_wave),
+            ..Default::default()
+        };
+        let summary = Packet {
+            poor_signal: Some(0),
+            attention: Some(50),
+            ..Default::default()
+        };
+        let packets =
+            [raw(1), raw(2), summary.clone(), summary.clone(), raw(3)].map(Ok::<_, Error>);
+        let coalesced: Vec<_> = Coalesce::new(stream::iter(packets), Duration::from_secs(1))
+            .map(|p| p.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            coalesced,
+            vec![
+                raw(1),
+                Packet {
+                    raw_wave: Some(2),
+                    ..summary.clone()
+                },
+                summary,
+                raw(3),
+            ]
+        );
+    }
+}
@@ -5,52 +5,130 @@
 //! # Enums
 //!
 //! - `Code`: Represents various data codes used in the NeuroSky device communication.
+//! - `Band`: Represents the frequency bands of the EEG power spectrum.
+//! - `UnknownCodeAction`: What to do when packets keep containing unknown codes.
+//! - `OutOfRangeAction`: What to do with eSense values above 100.
+//! - `FramingMode`: Whether payload anomalies drop the frame or are parsed best-effort.
+//! - `PowerTransform`: Compression applied to the EEG power values for display.
+//! - `ReaderState`: Where a `DataReader` currently is in the framing of the stream.
+//! - `CommError`: Why `DataReader::poll_next` failed.
 //!
 //! # Structs
 //!
 //! - `Power`: Represents the EEG power spectrum values.
 //! - `Packet`: Represents a data packet received from the NeuroSky device.
+//! - `PacketDiff`: Lists the fields that changed between two packets.
 //! - `DataReader`: Reads and parses data packets from the bytes stream.
+//! - `Snapshot`: Captures the latest values seen by a `DataReader`.
+//! - `SessionReport`: Reports on the data quality of a recording (see `analyze`).
+//! - `ReaderStats`: Counts the valid and corrupted frames decoded by a `DataReader`.
+//! - `PartialRead`: An error interrupting `DataReader::read_packets`, with the packets read before it.
 //!
 //! # Example
 //!
-//! ```rust
-//! use bluer::rfcomm::Stream;
-//! use cerebrust::comm::DataReader;
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig};
+//! use futures::StreamExt;
 //!
 //! #[tokio::main]
 //! async fn main() {
-//!     let stream = ...; // Build RFCOMM stream
+//!     let stream = DeviceConfig::default().connect().await.unwrap();
 //!     let mut reader = DataReader::new(stream);
 //!
-//!     while let Some(packet) = reader.next() {
+//!     while let Some(packet) = StreamExt::next(&mut reader).await {
 //!         println!("{:?}", packet);
 //!     }
 //! }
 //! ```
 //!
+//! # Stream contract
+//!
+//! `DataReader` implements `futures::Stream` with
+//! `Item = Result<Packet, CommError>`, so it composes with the `StreamExt`
+//! combinators. Corrupted frames are skipped, read errors are yielded as
+//! `Err` items, and `None` means the underlying stream ended cleanly between
+//! frames. A stream ending in the middle of a frame yields a
+//! `TruncatedPayload` error first. Adapters built on top of the reader accept
+//! any error convertible into `std::io::Error`, and forward items following
+//! the same contract with `std::io::Error` errors.
+//!
+//! # Diagnostics
+//!
+//! Checksum mismatches, payload anomalies and resynchronizations are
+//! reported on stderr. With the `log` feature enabled, they go through the
+//! `log` facade instead, at the `warn` level for checksum mismatches,
+//! unexpected value lengths and resynchronizations, and at the `debug` level
+//! for unknown, extended and sync codes.
+//!
+//! # Metrics
+//!
+//! With the `metrics` feature enabled, `DataReader` reports through the
+//! `metrics` facade, so any installed exporter picks them up:
+//!
+//! - `cerebrust.packets.total` (counter): Valid packets received.
+//! - `cerebrust.checksum.failures` (counter): Frames dropped due to a checksum mismatch.
+//! - `cerebrust.packet.rate` (gauge): Packets received per second.
+//! - `cerebrust.packet.jitter` (gauge): Standard deviation of raw wave packet intervals (s).
+//! - `cerebrust.signal.poor` (gauge): Latest signal quality value.
+//!
 //! # Errors
 //!
-//! The `poll_next` method in `DataReader` returns an `Error` if there is an issue
-//! reading from the stream or if the packet is corrupted.
+//! The `poll_next` method in `DataReader` returns a `CommError`, telling
+//! whether reading from the stream failed or the stream has ended
+//! (`CommError::Io`), or the stream ended in the middle of a frame
+//! (`CommError::TruncatedPayload`). Corrupted frames are skipped, unless
+//! `DataReader::with_frame_errors` is enabled, in which case they are
+//! returned as `CommError::ChecksumMismatch` or `CommError::InvalidLength`.
+//! eSense values out of range are returned as `CommError::OutOfRange` with
+//! `DataReader::with_out_of_range(OutOfRangeAction::Error)`.
+//! `CommError` converts into `std::io::Error`, so `?` works in functions
+//! returning `std::io::Result`.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::poll_fn,
+    io::{Error, ErrorKind},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
 
-use std::io::Error;
+use bluer::rfcomm;
+use futures::{Stream, StreamExt, executor::block_on, future::ready};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    sync::broadcast,
+};
 
-use bluer::rfcomm::Stream;
-use futures::executor::block_on;
-use tokio::io::AsyncReadExt;
+use crate::{
+    channel::{OverflowPolicy, Receiver, Subscriptions, channel, fanout},
+    classify::{Classifier, StateLabel},
+    command::{Command, DeviceWriter},
+    quality::SignalQuality,
+    summary::{SessionStats, SessionSummary},
+};
 
 /// Represents the different data codes used in the NeuroSky device communication.
 /// Each code corresponds to a specific type of data that can be received from
 /// the device. The codes are defined as per the NeuroSky protocol.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Code {
+pub enum Code {
+    /// Single-byte u8
+    Battery = 0x01,
     /// Single-byte u8
     PoorSignal = 0x02,
     /// Single-byte u8
     Attention = 0x04,
     /// Single-byte u8
     Meditation = 0x05,
+    /// Single-byte u8
+    RawWave8 = 0x06,
+    /// Single-byte u8
+    RawMarker = 0x07,
+    /// Single-byte u8
+    BlinkStrength = 0x16,
     /// Multi-byte i16
     RawWave = 0x80,
     /// Multi-byte u24 * 8
@@ -66,9 +144,13 @@ enum Code {
 impl From<u8> for Code {
     fn from(value: u8) -> Self {
         match value {
+            0x01 => Code::Battery,
             0x02 => Code::PoorSignal,
             0x04 => Code::Attention,
             0x05 => Code::Meditation,
+            0x06 => Code::RawWave8,
+            0x07 => Code::RawMarker,
+            0x16 => Code::BlinkStrength,
             0x80 => Code::RawWave,
             0x83 => Code::AsicEegPower,
             0x55 => Code::Extended,
@@ -78,8 +160,61 @@ impl From<u8> for Code {
     }
 }
 
+/// Represents the frequency bands of the EEG power spectrum, ordered from
+/// the lowest to the highest frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Band {
+    /// Delta (0.5 ~ 2.75 Hz)
+    Delta,
+    /// Theta (3.5 ~ 7.75 Hz)
+    Theta,
+    /// Low alpha (7.5 ~ 9.25 Hz)
+    LowAlpha,
+    /// High alpha (9.5 ~ 11.75 Hz)
+    HighAlpha,
+    /// Low beta (12 ~ 14.75 Hz)
+    LowBeta,
+    /// High beta (15 ~ 21.75 Hz)
+    HighBeta,
+    /// Low gamma (22 ~ 30.75 Hz)
+    LowGamma,
+    /// Mid gamma (31 ~ 100 Hz)
+    MidGamma,
+}
+
+impl Band {
+    /// All bands, in the order of `Power::as_array`.
+    pub const ALL: [Band; 8] = [
+        Band::Delta,
+        Band::Theta,
+        Band::LowAlpha,
+        Band::HighAlpha,
+        Band::LowBeta,
+        Band::HighBeta,
+        Band::LowGamma,
+        Band::MidGamma,
+    ];
+
+    /// Returns the name of the band, as the field of `Power` holding it,
+    /// e.g. `low_alpha`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Band::Delta => "delta",
+            Band::Theta => "theta",
+            Band::LowAlpha => "low_alpha",
+            Band::HighAlpha => "high_alpha",
+            Band::LowBeta => "low_beta",
+            Band::HighBeta => "high_beta",
+            Band::LowGamma => "low_gamma",
+            Band::MidGamma => "mid_gamma",
+        }
+    }
+}
+
 /// Represents the EEG power spectrum values.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Power {
     /// Delta (0.5 ~ 2.75 Hz)
     pub delta: u32,
@@ -99,29 +234,465 @@ pub struct Power {
     pub mid_gamma: u32,
 }
 
-/// Represents a data packet received from the NeuroSky device.
+impl Power {
+    /// Returns the band values in the order delta, theta, low alpha,
+    /// high alpha, low beta, high beta, low gamma, mid gamma, i.e. the order
+    /// of `Band::ALL`, e.g. to feed a chart library.
+    pub fn as_array(&self) -> [u32; 8] {
+        [
+            self.delta,
+            self.theta,
+            self.low_alpha,
+            self.high_alpha,
+            self.low_beta,
+            self.high_beta,
+            self.low_gamma,
+            self.mid_gamma,
+        ]
+    }
+
+    /// Returns the name and value of each band, in the order of `Band::ALL`,
+    /// e.g. to write a CSV header and row.
+    pub fn bands(&self) -> [(&'static str, u32); 8] {
+        let values = self.as_array();
+        std::array::from_fn(|i| (Band::ALL[i].name(), values[i]))
+    }
+
+    /// Converts the spectrum into a map from band to value, ordered from the
+    /// lowest to the highest frequency.
+    pub fn to_map(&self) -> BTreeMap<Band, u32> {
+        Band::ALL.into_iter().zip(self.as_array()).collect()
+    }
+
+    /// Builds a spectrum from a map from band to value. Bands missing from
+    /// the map are zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - The value of each band.
+    ///
+    /// # Returns
+    ///
+    /// * `Power` - The spectrum.
+    pub fn from_map(map: &BTreeMap<Band, u32>) -> Power {
+        let value = |band| map.get(&band).copied().unwrap_or_default();
+        Power {
+            delta: value(Band::Delta),
+            theta: value(Band::Theta),
+            low_alpha: value(Band::LowAlpha),
+            high_alpha: value(Band::HighAlpha),
+            low_beta: value(Band::LowBeta),
+            high_beta: value(Band::HighBeta),
+            low_gamma: value(Band::LowGamma),
+            mid_gamma: value(Band::MidGamma),
+        }
+    }
+
+    /// Returns the sum of all bands.
+    pub fn total(&self) -> u64 {
+        self.as_array().iter().map(|&x| x as u64).sum()
+    }
+
+    /// Returns each band as a fraction of the total power, in the order
+    /// delta, theta, low alpha, high alpha, low beta, high beta, low gamma,
+    /// mid gamma. The fractions sum to 1.0, or are all zero if the total
+    /// power is zero.
+    pub fn relative(&self) -> [f64; 8] {
+        let total = self.total();
+        if total == 0 {
+            return [0.0; 8];
+        }
+        self.as_array().map(|x| x as f64 / total as f64)
+    }
+
+    /// Returns the delta power, for symmetry with the grouped bands.
+    pub fn delta(&self) -> u64 {
+        self.delta as u64
+    }
+
+    /// Returns the theta power, for symmetry with the grouped bands.
+    pub fn theta(&self) -> u64 {
+        self.theta as u64
+    }
+
+    /// Returns the total alpha power, i.e. low alpha + high alpha.
+    pub fn alpha(&self) -> u64 {
+        self.low_alpha as u64 + self.high_alpha as u64
+    }
+
+    /// Returns the total beta power, i.e. low beta + high beta.
+    pub fn beta(&self) -> u64 {
+        self.low_beta as u64 + self.high_beta as u64
+    }
+
+    /// Returns the total gamma power, i.e. low gamma + mid gamma.
+    pub fn gamma(&self) -> u64 {
+        self.low_gamma as u64 + self.mid_gamma as u64
+    }
+}
+
+/// Represents a transform applied to each EEG power band, e.g. to compress
+/// values spanning several orders of magnitude for a linear display.
 #[derive(Debug, Default, Clone, Copy)]
+pub enum PowerTransform {
+    /// Keep the values as they are
+    #[default]
+    Identity,
+    /// Square root, i.e. amplitude instead of power
+    Sqrt,
+    /// Natural logarithm of 1 + value, so a zero power stays zero
+    Log,
+    /// Any other transform
+    Custom(fn(u32) -> f64),
+}
+
+impl PowerTransform {
+    /// Applies the transform to each band, in the order of `Band::ALL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `power` - The EEG power spectrum to transform.
+    ///
+    /// # Returns
+    ///
+    /// * `[f64; 8]` - The transformed value of each band.
+    pub fn apply(&self, power: &Power) -> [f64; 8] {
+        power.as_array().map(|x| match self {
+            PowerTransform::Identity => x as f64,
+            PowerTransform::Sqrt => (x as f64).sqrt(),
+            PowerTransform::Log => (x as f64).ln_1p(),
+            PowerTransform::Custom(f) => f(x),
+        })
+    }
+}
+
+/// Formats the spectrum on a single line, e.g. `delta=1 theta=2 ... mid_gamma=8`.
+impl fmt::Display for Power {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.bands().into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Largest valid eSense value, i.e. attention and meditation.
+pub const ESENSE_MAX: u8 = 100;
+
+/// Represents a data packet received from the NeuroSky device. Serialized
+/// without the fields that are `None`.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Packet {
     /// Signal quality (0 ~ 255)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub poor_signal: Option<u8>,
     /// Attention eSense (0 ~ 100)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub attention: Option<u8>,
     /// Meditation eSense (0 ~ 100)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub meditation: Option<u8>,
+    /// Blink strength (0 ~ 255), reported when a blink is detected
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub blink_strength: Option<u8>,
+    /// Battery level (0 ~ 255), reported periodically by some devices, e.g.
+    /// the MyndBand
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub battery: Option<u8>,
     /// Raw wave value (-32768 ~ 32767)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub raw_wave: Option<i16>,
+    /// Reduced-precision raw wave value (0 ~ 255), sent by some firmware
+    /// variants instead of, or along with, `raw_wave`. It is kept apart as
+    /// its scale differs: prefer `raw_wave` when a device sends both, and do
+    /// not mix the two in a single signal.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub raw_wave8: Option<u8>,
+    /// Raw marker (0 ~ 255), inserted into the stream to align external
+    /// events, e.g. stimuli, with the raw wave timeline
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub raw_marker: Option<u8>,
     /// EEG power spectrum values (uV^2)
     /// Delta (0.5 ~ 2.75 Hz)
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub eeg_power: Option<Power>,
+    /// EEG power spectrum with the transform of the reader applied, in the
+    /// order of `Band::ALL`. Only set if a transform is configured, see
+    /// `DataReader::with_power_transform`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub transformed_power: Option<[f64; 8]>,
+    /// Mental state assigned by the classifier of the reader. Only set if a
+    /// classifier is configured, see `DataReader::with_classifier`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub state: Option<StateLabel>,
+    /// Instant the frame of the packet was read and validated, set by
+    /// `DataReader`. Use it to measure the intervals between packets, e.g. to
+    /// reconstruct the timeline of raw wave samples. Not serialized, as an
+    /// `Instant` is only meaningful within the running process.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub received_at: Option<Instant>,
+    /// Wall-clock time the frame of the packet was read and validated, set by
+    /// `DataReader`, e.g. to log packets to disk. Unlike `received_at`, it may
+    /// jump when the system clock is adjusted.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub received_time: Option<SystemTime>,
+    /// Payload of the frame the packet was decoded from, without sync bytes,
+    /// length and checksum, e.g. to inspect codes that are not parsed. Only
+    /// set if enabled, see `DataReader::with_keep_raw`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub raw: Option<Vec<u8>>,
+}
+
+/// Formats the fields that are present on a single line, e.g.
+/// `sig=0 att=57 med=42`, followed by the EEG power spectrum, if any. A
+/// packet without any field is formatted as `(empty)`. Timestamps, the
+/// transformed spectrum and the raw payload are left out; use `Debug` for
+/// all details.
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.map(|state| format!("{:?}", state));
+        let fields: [(&str, Option<&dyn fmt::Display>); 9] = [
+            ("sig", self.poor_signal.as_ref().map(|x| x as _)),
+            ("att", self.attention.as_ref().map(|x| x as _)),
+            ("med", self.meditation.as_ref().map(|x| x as _)),
+            ("blink", self.blink_strength.as_ref().map(|x| x as _)),
+            ("bat", self.battery.as_ref().map(|x| x as _)),
+            ("raw", self.raw_wave.as_ref().map(|x| x as _)),
+            ("raw8", self.raw_wave8.as_ref().map(|x| x as _)),
+            ("marker", self.raw_marker.as_ref().map(|x| x as _)),
+            ("state", state.as_ref().map(|x| x as _)),
+        ];
+        let mut sep = "";
+        for (name, value) in fields {
+            if let Some(value) = value {
+                write!(f, "{}{}={}", sep, name, value)?;
+                sep = " ";
+            }
+        }
+        match self.eeg_power {
+            Some(power) => write!(f, "{}{}", sep, power),
+            None if sep.is_empty() => f.write_str("(empty)"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Packet {
+    /// Checks that the eSense values of the packet, if any, are within
+    /// 0 ~ `ESENSE_MAX`. A checksum only catches most corruptions, so a
+    /// corrupted value may still pass it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `CommError::OutOfRange` for the first
+    /// eSense value above `ESENSE_MAX`.
+    pub fn validate(&self) -> Result<(), CommError> {
+        let esense = [
+            ("attention", self.attention),
+            ("meditation", self.meditation),
+        ];
+        for (field, value) in esense {
+            if let Some(value) = value.filter(|&x| x > ESENSE_MAX) {
+                return Err(CommError::OutOfRange { field, value });
+            }
+        }
+        Ok(())
+    }
+
+    /// Interprets the signal quality of the packet.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<SignalQuality>` - The meaning of `poor_signal`, `None` if the
+    ///   packet does not report it.
+    pub fn signal_quality(&self) -> Option<SignalQuality> {
+        self.poor_signal.map(SignalQuality::from)
+    }
+
+    /// Compares this packet with another one, listing the fields whose
+    /// values differ. Values of `self` are reported as old values and values
+    /// of `other` as new values.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The packet to compare with.
+    ///
+    /// # Returns
+    ///
+    /// * `PacketDiff` - The changed fields, in declaration order.
+    pub fn diff(&self, other: &Packet) -> PacketDiff {
+        let mut changes = Vec::new();
+        if self.poor_signal != other.poor_signal {
+            changes.push(FieldChange::PoorSignal {
+                old: self.poor_signal,
+                new: other.poor_signal,
+            });
+        }
+        if self.attention != other.attention {
+            changes.push(FieldChange::Attention {
+                old: self.attention,
+                new: other.attention,
+            });
+        }
+        if self.meditation != other.meditation {
+            changes.push(FieldChange::Meditation {
+                old: self.meditation,
+                new: other.meditation,
+            });
+        }
+        if self.blink_strength != other.blink_strength {
+            changes.push(FieldChange::BlinkStrength {
+                old: self.blink_strength,
+                new: other.blink_strength,
+            });
+        }
+        if self.battery != other.battery {
+            changes.push(FieldChange::Battery {
+                old: self.battery,
+                new: other.battery,
+            });
+        }
+        if self.raw_wave != other.raw_wave {
+            changes.push(FieldChange::RawWave {
+                old: self.raw_wave,
+                new: other.raw_wave,
+            });
+        }
+        if self.raw_wave8 != other.raw_wave8 {
+            changes.push(FieldChange::RawWave8 {
+                old: self.raw_wave8,
+                new: other.raw_wave8,
+            });
+        }
+        if self.raw_marker != other.raw_marker {
+            changes.push(FieldChange::RawMarker {
+                old: self.raw_marker,
+                new: other.raw_marker,
+            });
+        }
+        if self.eeg_power != other.eeg_power {
+            changes.push(FieldChange::EegPower {
+                old: self.eeg_power,
+                new: other.eeg_power,
+            });
+        }
+        PacketDiff { changes }
+    }
+
+    /// Encodes the packet as a ThinkGear frame, sync bytes and checksum
+    /// included, e.g. to forward it to another machine. EEG power values
+    /// above the 24-bit range are saturated. Fields computed or kept by the
+    /// reader, i.e. `transformed_power`, `state` and `raw`, are not encoded.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<u8>` - The frame, decoded back into the same packet.
+    pub fn to_frame(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(38);
+        let single = [
+            (Code::PoorSignal, self.poor_signal),
+            (Code::Attention, self.attention),
+            (Code::Meditation, self.meditation),
+            (Code::BlinkStrength, self.blink_strength),
+            (Code::Battery, self.battery),
+            (Code::RawWave8, self.raw_wave8),
+            (Code::RawMarker, self.raw_marker),
+        ];
+        for (code, value) in single {
+            if let Some(value) = value {
+                payload.extend([code as u8, value]);
+            }
+        }
+        if let Some(raw_wave) = self.raw_wave {
+            payload.extend([Code::RawWave as u8, 2]);
+            payload.extend(raw_wave.to_be_bytes());
+        }
+        if let Some(power) = self.eeg_power {
+            payload.extend([Code::AsicEegPower as u8, 24]);
+            for value in power.as_array() {
+                payload.extend(&value.min(0xFF_FFFF).to_be_bytes()[1..]);
+            }
+        }
+        let mut frame = vec![Code::Sync as u8, Code::Sync as u8, payload.len() as u8];
+        frame.extend(&payload);
+        frame.push(checksum(&payload));
+        frame
+    }
+}
+
+/// Represents a field of a `Packet` that changed between two packets, with
+/// its old and new values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldChange {
+    PoorSignal {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    Attention {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    Meditation {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    BlinkStrength {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    Battery {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    RawWave {
+        old: Option<i16>,
+        new: Option<i16>,
+    },
+    RawWave8 {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    RawMarker {
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+    EegPower {
+        old: Option<Power>,
+        new: Option<Power>,
+    },
+}
+
+/// Represents the fields that changed between two packets, as returned by
+/// `Packet::diff`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PacketDiff {
+    /// The changed fields, in declaration order
+    pub changes: Vec<FieldChange>,
+}
+
+impl PacketDiff {
+    /// Whether no field changed.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
 }
 
 /// Represents the different types of packets that can be received from the
 /// NeuroSky device. Each variant corresponds to a specific combination of data
-/// that can be received.
-#[derive(Debug, Clone, Copy)]
+/// that can be received. Serialized with the name of the variant in a `type`
+/// field, next to the values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type"))]
 pub enum PacketVariant {
     /// Packet with only raw wave value @ 512Hz
     RawWave { raw_wave: i16 },
+    /// Packet with the strength of a detected blink
+    Blink { blink_strength: u8 },
     /// Packet with eSense values and EEG power spectrum @ 1Hz
     EegPower {
         poor_signal: u8,
@@ -129,15 +700,46 @@ pub enum PacketVariant {
         meditation: u8,
         eeg_power: Power,
     },
+    /// Packet with eSense values and the EEG power spectrum normalized to
+    /// fractions of the total power (see `Power::relative`) @ 1Hz
+    RelativePower {
+        poor_signal: u8,
+        attention: u8,
+        meditation: u8,
+        relative_power: [f64; 8],
+    },
+}
+
+impl PacketVariant {
+    /// Converts an `EegPower` variant into a `RelativePower` variant. Other
+    /// variants are returned unchanged.
+    pub fn into_relative(self) -> PacketVariant {
+        match self {
+            PacketVariant::EegPower {
+                poor_signal,
+                attention,
+                meditation,
+                eeg_power,
+            } => PacketVariant::RelativePower {
+                poor_signal,
+                attention,
+                meditation,
+                relative_power: eeg_power.relative(),
+            },
+            other => other,
+        }
+    }
 }
 
 impl TryInto<PacketVariant> for Packet {
-    type Error = &'static str;
+    type Error = CommError;
 
     /// Attempts to convert the `Packet` into a `PacketVariant`. If the packet
     /// contains both eSense values and EEG power spectrum, it returns the
-    /// `EegPower` variant. If it contains only the raw wave value, it returns
-    /// the `RawWave` variant. If neither is present, it returns an error.
+    /// `EegPower` variant. If it contains a blink strength, it returns the
+    /// `Blink` variant. If it contains only the raw wave value, it returns
+    /// the `RawWave` variant. If none is present, it returns
+    /// `CommError::UnknownVariant`.
     fn try_into(self) -> Result<PacketVariant, Self::Error> {
         if let (Some(poor_signal), Some(attention), Some(meditation), Some(eeg_power)) = (
             self.poor_signal,
@@ -151,144 +753,3026 @@ impl TryInto<PacketVariant> for Packet {
                 meditation,
                 eeg_power,
             })
+        } else if let Some(blink_strength) = self.blink_strength {
+            Ok(PacketVariant::Blink { blink_strength })
         } else if let Some(raw_wave) = self.raw_wave {
             Ok(PacketVariant::RawWave { raw_wave })
         } else {
-            Err("Invalid packet type")
+            Err(CommError::UnknownVariant)
         }
     }
 }
 
-/// Represents a data reader that reads and parses data packets from the
-/// NeuroSky device.
-pub struct DataReader {
-    /// The RFCOMM stream to read data from
-    stream: Stream,
+/// A snapshot of the latest values seen by a `DataReader`, suitable for
+/// rendering a dashboard in a single pass.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Snapshot {
+    /// Latest signal quality (0 ~ 255)
+    pub poor_signal: Option<u8>,
+    /// Latest attention eSense (0 ~ 100)
+    pub attention: Option<u8>,
+    /// Latest meditation eSense (0 ~ 100)
+    pub meditation: Option<u8>,
+    /// Latest EEG power spectrum values (uV^2)
+    pub eeg_power: Option<Power>,
+    /// Latest battery level (0 ~ 255), if reported by the device
+    pub battery: Option<u8>,
+    /// Packets received per second, measured over the last full second
+    pub packet_rate: f64,
+    /// Standard deviation of the intervals between raw wave packets,
+    /// measured over the last full second. High jitter at a stable packet
+    /// rate points at buffering or scheduling problems rather than loss.
+    pub jitter: Option<Duration>,
+    /// Latest signal strength of the connection (dBm), if reported
+    pub rssi: Option<i16>,
+    /// Whether the last frame read from the stream was valid
+    pub synced: bool,
 }
 
-impl DataReader {
-    pub fn new(stream: Stream) -> DataReader {
-        DataReader { stream }
+/// Thresholds the values seen by a `DataReader` must meet for it to report a
+/// signal lock, see `DataReader::has_lock`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LockThresholds {
+    /// Minimum packets received per second. Default: 0.5, which a device in
+    /// normal output mode meets. Devices in raw output mode send about 512
+    /// packets per second, so a few hundred detects losses sooner.
+    pub min_packet_rate: f64,
+    /// Worst signal quality (0 ~ 255), 0 meaning a perfect signal and 200 no
+    /// contact at all. Default: 50.
+    pub max_poor_signal: u8,
+}
+
+impl Default for LockThresholds {
+    fn default() -> Self {
+        LockThresholds {
+            min_packet_rate: 0.5,
+            max_poor_signal: 50,
+        }
     }
+}
 
-    /// Reads the next data packet from the stream. It synchronizes with the
-    /// NeuroSky device, reads the packet length, and then reads the payload
-    /// and checksum. It verifies the checksum and parses the payload into a
-    /// `Packet` struct. If the packet is corrupted or invalid, it retries
-    /// reading the packet until a valid one is received.
-    pub async fn poll_next(&mut self) -> Result<Packet, Error> {
-        let mut packet = Packet::default();
-        loop {
-            let mut sync = 0;
-            while sync < 2 {
+impl LockThresholds {
+    /// Tells whether a snapshot meets the thresholds: the framing is synced,
+    /// the packet rate is at least `min_packet_rate`, and the signal quality
+    /// has been reported and is at most `max_poor_signal`.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - The latest values seen by a reader.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the snapshot meets the thresholds.
+    pub fn is_met(&self, snapshot: &Snapshot) -> bool {
+        snapshot.synced
+            && snapshot.packet_rate >= self.min_packet_rate
+            && snapshot
+                .poor_signal
+                .is_some_and(|x| x <= self.max_poor_signal)
+    }
+}
+
+/// Running mean and variance of the intervals between events, using
+/// Welford's online algorithm.
+#[derive(Debug, Default)]
+struct IntervalStats {
+    /// Instant of the last event
+    last: Option<Instant>,
+    /// Number of intervals
+    count: u32,
+    /// Mean interval (s)
+    mean: f64,
+    /// Sum of squared differences from the mean (s^2)
+    m2: f64,
+}
+
+impl IntervalStats {
+    /// Records an event that happened at `now`.
+    fn record(&mut self, now: Instant) {
+        if let Some(last) = self.last.replace(now) {
+            self.push(now.duration_since(last));
+        }
+    }
+
+    /// Adds an interval to the statistics.
+    fn push(&mut self, interval: Duration) {
+        let x = interval.as_secs_f64();
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Returns the standard deviation of the intervals, `None` with fewer
+    /// than two intervals.
+    fn std_dev(&self) -> Option<Duration> {
+        (self.count >= 2).then(|| Duration::from_secs_f64((self.m2 / self.count as f64).sqrt()))
+    }
+
+    /// Starts a new measurement window, keeping the last event so the next
+    /// interval spans both windows.
+    fn reset(&mut self) {
+        *self = IntervalStats {
+            last: self.last,
+            ..Default::default()
+        };
+    }
+}
+
+/// Represents the packets collected by `DataReader::read_until`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Collected {
+    /// Packets collected, in arrival order. If `matched`, the last one is the
+    /// packet that satisfied the predicate.
+    pub packets: Vec<Packet>,
+    /// Whether the predicate was satisfied before the timeout
+    pub matched: bool,
+}
+
+/// Represents running counts of the frames decoded by a `DataReader`, to
+/// measure the quality of the link, see `DataReader::stats`. Unlike
+/// `SessionSummary`, frames received during the warmup period are counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderStats {
+    /// Number of valid frames
+    pub packets_ok: u64,
+    /// Number of frames dropped due to a checksum mismatch
+    pub checksum_errors: u64,
+    /// Number of frames dropped due to an invalid payload length
+    pub invalid_lengths: u64,
+    /// Number of frames dropped due to a payload anomaly in strict framing
+    /// mode, see `FramingMode::Strict`
+    pub rejected: u64,
+    /// Number of unknown codes in valid frames
+    pub unknown_codes: u64,
+    /// Number of frames cut off by the end of the stream
+    pub truncated: u64,
+}
+
+/// Represents the errors that can occur while reading packets.
+#[derive(Debug)]
+pub enum CommError {
+    /// Reading from the stream failed, or the stream has ended
+    Io(Error),
+    /// The checksum of a frame did not match its payload
+    ChecksumMismatch { expected: u8, got: u8 },
+    /// A frame declared a payload longer than the reader accepts
+    InvalidLength(usize),
+    /// The stream, or the bytes given to `parse_frame`, ended in the middle
+    /// of a frame
+    TruncatedPayload,
+    /// The bytes given to `parse_frame` do not start with sync bytes
+    MissingSync,
+    /// The packet matches no `PacketVariant`, e.g. it only holds a battery
+    /// level
+    UnknownVariant,
+    /// An eSense value of a packet is above `ESENSE_MAX`
+    OutOfRange { field: &'static str, value: u8 },
+}
+
+impl fmt::Display for CommError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommError::Io(e) => e.fmt(f),
+            CommError::ChecksumMismatch { expected, got } => write!(
+                f,
+                "Checksum mismatch: 0b{:08b} (Expected) != 0b{:08b} (Got)",
+                expected, got
+            ),
+            CommError::InvalidLength(len) => write!(f, "Invalid payload length: {}", len),
+            CommError::TruncatedPayload => write!(f, "Stream ended in the middle of a packet"),
+            CommError::MissingSync => write!(f, "Frame does not start with sync bytes"),
+            CommError::UnknownVariant => write!(f, "Packet matches no packet variant"),
+            CommError::OutOfRange { field, value } => write!(
+                f,
+                "{} out of range: {} (Expected 0 ~ {})",
+                field, value, ESENSE_MAX
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CommError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CommError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for CommError {
+    fn from(e: Error) -> Self {
+        CommError::Io(e)
+    }
+}
+
+impl From<CommError> for Error {
+    fn from(e: CommError) -> Self {
+        match e {
+            CommError::Io(e) => e,
+            CommError::TruncatedPayload => Error::new(ErrorKind::UnexpectedEof, e),
+            _ => Error::new(ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Represents an error that interrupted `DataReader::read_packets` or
+/// `DataReader::read_for`, along with the packets read before it, so they are
+/// not lost.
+#[derive(Debug)]
+pub struct PartialRead {
+    /// Packets read before the error, in arrival order
+    pub packets: Vec<Packet>,
+    /// The error that interrupted the read
+    pub error: CommError,
+}
+
+impl fmt::Display for PartialRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (after {} packets)", self.error, self.packets.len())
+    }
+}
+
+impl std::error::Error for PartialRead {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PartialRead> for Error {
+    fn from(e: PartialRead) -> Self {
+        e.error.into()
+    }
+}
+
+/// Outcome of feeding a complete frame through the `Decoder`.
+#[derive(Debug)]
+pub(crate) enum Frame {
+    /// A valid packet
+    Packet(Packet),
+    /// The frame's checksum did not match its payload
+    ChecksumMismatch { expected: u8, got: u8 },
+    /// The frame declared an invalid payload length
+    InvalidLength(usize),
+    /// The frame's payload had an anomaly and strict framing is enabled
+    Rejected,
+}
+
+/// Position of the `Decoder` in the framing state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// Waiting for two consecutive sync bytes
+    Sync { count: u8 },
+    /// Waiting for the payload length byte
+    Length,
+    /// Collecting `len` payload bytes
+    Payload { len: usize },
+    /// Waiting for the checksum byte
+    Checksum,
+    /// A valid frame just ended, waiting for the sync bytes of the next one
+    Next,
+}
+
+/// Represents where a `DataReader` currently is in the framing of the byte
+/// stream, as returned by `DataReader::state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReaderState {
+    /// Between frames, right after a valid one
+    Idle,
+    /// Hunting for the sync bytes starting a frame, `found` of which were
+    /// already seen
+    SearchingSync { found: u8 },
+    /// Waiting for the payload length byte
+    ReadingLength,
+    /// Collecting the payload, `remaining` bytes of which are still expected
+    ReadingPayload { remaining: usize },
+    /// Waiting for the checksum byte
+    ReadingChecksum,
+    /// The stream has ended or failed, no more packets will be read
+    Ended,
+}
+
+/// Largest payload length allowed by the ThinkGear framing (bytes).
+pub const MAX_PAYLOAD_LEN: usize = 169;
+
+/// Represents how a `DataReader` handles anomalies in the payload of a frame
+/// whose checksum is valid: unexpected value lengths, values cut off by the
+/// end of the payload, unknown codes, and extended or sync codes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FramingMode {
+    /// Drop the whole frame on any anomaly, so no value is parsed from data
+    /// that may be corrupted. Suited to recording clean data.
+    Strict,
+    /// Log anomalies and parse as much of the payload as possible. A frame
+    /// directly following a valid one without sync bytes is also decoded,
+    /// provided its checksum is valid and all its codes are known.
+    #[default]
+    Lenient,
+}
+
+/// Byte-by-byte decoder of the ThinkGear framing. It does not perform any
+/// I/O, so the same logic serves any source of bytes.
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    /// Current position in the framing state machine
+    framing: Framing,
+    /// Payload collected so far for the current frame, reused across frames
+    payload: Vec<u8>,
+    /// Wrapping sum of the payload collected so far
+    sum: u8,
+    /// Number of unknown codes in the last valid frame
+    unknown_codes: usize,
+    /// How payload anomalies are handled
+    mode: FramingMode,
+    /// Largest payload length accepted
+    max_payload: usize,
+    /// Bytes of a frame read without sync bytes, if one is being attempted
+    speculative: Option<Vec<u8>>,
+    /// Whether the payload is attached to the decoded packets
+    keep_raw: bool,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Decoder {
+        Decoder {
+            framing: Framing::Sync { count: 0 },
+            payload: Vec::with_capacity(MAX_PAYLOAD_LEN),
+            sum: 0,
+            unknown_codes: 0,
+            mode: FramingMode::default(),
+            max_payload: MAX_PAYLOAD_LEN,
+            speculative: None,
+            keep_raw: false,
+        }
+    }
+
+    /// Drops the partial frame, if any, and waits for the next sync bytes.
+    fn reset(&mut self) {
+        self.framing = Framing::Sync { count: 0 };
+        self.speculative = None;
+    }
+
+    /// Whether the decoder is between frames, i.e. no partial frame would be
+    /// lost if the stream ended now. A frame attempted without sync bytes is
+    /// only a guess, so it does not count as a partial frame.
+    pub(crate) fn is_idle(&self) -> bool {
+        matches!(self.framing, Framing::Sync { .. } | Framing::Next) || self.speculative.is_some()
+    }
+
+    /// Returns the current position in the framing state machine.
+    fn state(&self) -> ReaderState {
+        match self.framing {
+            Framing::Next => ReaderState::Idle,
+            Framing::Sync { count } => ReaderState::SearchingSync { found: count },
+            Framing::Length => ReaderState::ReadingLength,
+            Framing::Payload { len } => ReaderState::ReadingPayload {
+                remaining: len - self.payload.len(),
+            },
+            Framing::Checksum => ReaderState::ReadingChecksum,
+        }
+    }
+
+    /// Starts collecting a payload of `len` bytes.
+    fn start_payload(&mut self, len: usize) {
+        self.payload.clear();
+        self.sum = 0;
+        self.framing = match len {
+            0 => Framing::Checksum,
+            _ => Framing::Payload { len },
+        };
+    }
+
+    /// Gives up on a frame attempted without sync bytes, and hunts for sync
+    /// bytes in the bytes it consumed, except its length byte. Returns the
+    /// last frame found in them, if any.
+    fn abandon_speculative(&mut self) -> Option<Frame> {
+        let bytes = self.speculative.take()?;
+        self.framing = Framing::Sync { count: 0 };
+        bytes[1..]
+            .iter()
+            .fold(None, |frame, &b| self.push(b).or(frame))
+    }
+
+    /// Feeds a single byte to the decoder, returning a `Frame` once a full
+    /// frame has been read.
+    pub(crate) fn push(&mut self, byte: u8) -> Option<Frame> {
+        match self.framing {
+            Framing::Sync { count } => {
                 // Sync with the NeuroSky device until two sync bytes are received
-                if self.stream.read_u8().await? == Code::Sync as u8 {
-                    sync += 1;
+                self.framing = match (byte == Code::Sync as u8, count) {
+                    (true, 1) => Framing::Length,
+                    (true, _) => Framing::Sync { count: 1 },
+                    (false, _) => Framing::Sync { count: 0 },
+                };
+                None
+            }
+            Framing::Length => {
+                let len = byte as usize;
+                if len == Code::Sync as usize {
+                    // Re-read the packet length if it is another sync byte
+                    None
+                } else if len > self.max_payload {
+                    // Start-over if the packet length is invalid
+                    self.framing = Framing::Sync { count: 0 };
+                    Some(Frame::InvalidLength(len))
                 } else {
-                    sync = 0;
+                    self.start_payload(len);
+                    None
                 }
             }
-            let mut packet_length = self.stream.read_u8().await? as usize;
-            while packet_length == Code::Sync as usize {
-                // Re-read the packet length if it is another sync byte
-                packet_length = self.stream.read_u8().await? as usize;
-            }
-            if packet_length > Code::Sync as usize {
-                // Start-over if the packet length is invalid
-                continue;
-            }
-            // Read the payload and checksum
-            let mut payload = vec![0u8; packet_length as usize];
-            self.stream.read_exact(&mut payload).await?;
-            let checksum = self.stream.read_u8().await?;
-            // Verify the checksum
-            let calculated_checksum = 255 - payload.iter().fold(0u8, |acc, &x| acc.wrapping_add(x));
-            if calculated_checksum != checksum {
-                // Start-over if the packet is corrupted
-                eprintln!(
-                    "Checksum mismatch: 0b{:08b} (Expected) != 0b{:08b} (Got)",
-                    checksum, calculated_checksum
-                );
-                continue;
-            }
-            // Parse the payload
-            let mut i = 0..packet_length;
-            while let Some(idx) = i.next() {
-                match Code::from(payload[idx]) {
-                    // Single-byte codes
-                    Code::PoorSignal => packet.poor_signal = Some(payload[i.next().unwrap()]),
-                    Code::Attention => packet.attention = Some(payload[i.next().unwrap()]),
-                    Code::Meditation => packet.meditation = Some(payload[i.next().unwrap()]),
-
-                    // Multi-byte codes
-                    Code::RawWave => {
-                        let value_length = payload[i.next().unwrap()];
-                        if value_length != 2 {
-                            // Something is wrong with the data, but we don't know what
-                            eprintln!("Unexpected raw wave length {}", value_length);
-                        }
-                        packet.raw_wave = Some(i16::from_be_bytes([
-                            payload[i.next().unwrap()],
-                            payload[i.next().unwrap()],
-                        ]));
-                    }
-                    Code::AsicEegPower => {
-                        let value_length = payload[i.next().unwrap()];
-                        if value_length != 24 {
-                            // Something is wrong with the data, but we don't know what
-                            eprintln!("Unexpected ASIC EEG power length {}", value_length);
-                        }
-                        let mut values = [0; 8];
-                        values.iter_mut().for_each(|x| {
-                            *x = u32::from_be_bytes([
-                                0,
-                                payload[i.next().unwrap()],
-                                payload[i.next().unwrap()],
-                                payload[i.next().unwrap()],
-                            ]);
-                        });
-                        packet.eeg_power = Some(Power {
-                            delta: values[0],
-                            theta: values[1],
-                            low_alpha: values[2],
-                            high_alpha: values[3],
-                            low_beta: values[4],
-                            high_beta: values[5],
-                            low_gamma: values[6],
-                            mid_gamma: values[7],
+            Framing::Next => {
+                let len = byte as usize;
+                if byte == Code::Sync as u8 {
+                    self.framing = Framing::Sync { count: 1 };
+                } else if self.mode == FramingMode::Lenient && len > 0 && len <= self.max_payload {
+                    // The sync bytes of the next frame may have been dropped,
+                    // so attempt to read a frame right away
+                    self.speculative = Some(vec![byte]);
+                    self.start_payload(len);
+                } else {
+                    self.framing = Framing::Sync { count: 0 };
+                }
+                None
+            }
+            Framing::Payload { len } => {
+                if let Some(bytes) = &mut self.speculative {
+                    bytes.push(byte);
+                }
+                self.payload.push(byte);
+                self.sum = self.sum.wrapping_add(byte);
+                if self.payload.len() == len {
+                    self.framing = Framing::Checksum;
+                }
+                None
+            }
+            Framing::Checksum => {
+                self.framing = Framing::Next;
+                // Verify the checksum, accumulated while collecting the payload
+                let calculated_checksum = 255 - self.sum;
+                if let Some(bytes) = &mut self.speculative {
+                    bytes.push(byte);
+                    // Only keep frames that are fully understood, as the
+                    // checksum alone matches one in 256 random frames
+                    let plausible = calculated_checksum == byte
+                        && !self.payload.is_empty()
+                        && layout(&self.payload).iter().all(|(code, _)| {
+                            !matches!(code, Code::Unknown | Code::Extended | Code::Sync)
                         });
+                    if !plausible {
+                        return self.abandon_speculative();
                     }
-
-                    // Reserved code
-                    Code::Extended => {
-                        // Extended code level is undefined
-                        eprintln!("Extended code level is not defined");
-                    }
-                    Code::Sync => {
-                        // Sync code encountered
-                        eprintln!("Sync code encountered");
-                    }
-                    Code::Unknown => {
-                        // Unknown code encountered
-                        eprintln!("Unknown code at {}: 0x{}", idx, payload[idx]);
+                    self.speculative = None;
+                }
+                if calculated_checksum != byte {
+                    self.framing = Framing::Sync { count: 0 };
+                    // Start-over if the packet is corrupted
+                    return Some(Frame::ChecksumMismatch {
+                        expected: byte,
+                        got: calculated_checksum,
+                    });
+                }
+                match decode_payload(&self.payload, self.mode) {
+                    Some((mut packet, unknown_codes)) => {
+                        self.unknown_codes = unknown_codes;
+                        if self.keep_raw {
+                            packet.raw = Some(self.payload.clone());
+                        }
+                        Some(Frame::Packet(packet))
                     }
+                    None => Some(Frame::Rejected),
                 }
             }
-            return Ok(packet);
         }
     }
 }
 
-impl Iterator for DataReader {
-    type Item = Packet;
+/// Computes the checksum of a payload, i.e. the one's complement of the
+/// lowest byte of the sum of its bytes.
+///
+/// # Arguments
+///
+/// * `payload` - The payload of a frame, without sync bytes and length.
+///
+/// # Returns
+///
+/// * `u8` - The checksum expected after the payload.
+pub fn checksum(payload: &[u8]) -> u8 {
+    255 - payload.iter().fold(0u8, |acc, &x| acc.wrapping_add(x))
+}
 
-    /// Polls the next packet from the stream. It blocks until a valid packet
-    /// is received. If an error occurs while reading the packet, it stops
-    /// the iterator.
-    fn next(&mut self) -> Option<Self::Item> {
-        match block_on(self.poll_next()) {
-            Ok(packet) => Some(packet),
-            Err(_) => None, // Stop the iterator if an error occurs
+/// Builds a frame with sync bytes, length and checksum around `payload`,
+/// for the tests of any module.
+#[cfg(test)]
+pub(crate) fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xAA, 0xAA, payload.len() as u8];
+    bytes.extend_from_slice(payload);
+    bytes.push(checksum(payload));
+    bytes
+}
+
+/// Parses the payload of a frame into a `Packet`, the way a `DataReader` in
+/// lenient framing mode does: anomalies are logged, and as many values as
+/// possible are parsed. Useful to replay recorded payloads, or to test code
+/// consuming packets without a device.
+///
+/// # Arguments
+///
+/// * `payload` - The payload of a frame, without sync bytes, length and
+///   checksum.
+///
+/// # Returns
+///
+/// * `Result<Packet, CommError>` - The values found in the payload.
+///
+/// # Errors
+///
+/// This function will return `CommError::InvalidLength` if the payload is
+/// longer than `MAX_PAYLOAD_LEN`.
+pub fn parse_payload(payload: &[u8]) -> Result<Packet, CommError> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(CommError::InvalidLength(payload.len()));
+    }
+    let (packet, _) = decode_payload(payload, FramingMode::Lenient)
+        .expect("Lenient parsing never rejects a payload");
+    Ok(packet)
+}
+
+/// Parses a complete frame into a `Packet`, after checking its sync bytes,
+/// length and checksum. Bytes after the frame are ignored.
+///
+/// # Arguments
+///
+/// * `frame` - The frame, starting with its sync bytes.
+///
+/// # Returns
+///
+/// * `Result<Packet, CommError>` - The values found in the payload of the frame.
+///
+/// # Errors
+///
+/// This function will return `CommError::MissingSync` if the bytes do not
+/// start with two sync bytes, `CommError::InvalidLength` if the declared
+/// length is longer than `MAX_PAYLOAD_LEN`, `CommError::TruncatedPayload` if
+/// the bytes end before the checksum, and `CommError::ChecksumMismatch` if
+/// the checksum does not match the payload.
+pub fn parse_frame(frame: &[u8]) -> Result<Packet, CommError> {
+    let sync = Code::Sync as u8;
+    let (len, rest) = match frame {
+        [a, b, len, rest @ ..] if *a == sync && *b == sync => (*len as usize, rest),
+        _ if frame.iter().take(2).any(|&x| x != sync) => return Err(CommError::MissingSync),
+        _ => return Err(CommError::TruncatedPayload),
+    };
+    if len > MAX_PAYLOAD_LEN {
+        return Err(CommError::InvalidLength(len));
+    }
+    let (payload, rest) = rest.split_at(len.min(rest.len()));
+    let Some(&got) = rest.first().filter(|_| payload.len() == len) else {
+        return Err(CommError::TruncatedPayload);
+    };
+    let expected = checksum(payload);
+    if got != expected {
+        return Err(CommError::ChecksumMismatch { expected, got });
+    }
+    parse_payload(payload)
+}
+
+/// Describes the structure of a payload without parsing its values, e.g. to
+/// find out what an unsupported device variant is sending.
+///
+/// Codes below 0x80 carry a single byte of data, and codes from 0x80 carry
+/// a length byte followed by that many bytes of data, so the layout of
+/// unknown codes is reported as well. Extended code bytes (0x55) are
+/// reported with a length of zero. An unknown code can be located in the
+/// payload by summing the sizes of the previous entries: one byte for the
+/// code, one for the length byte of codes from 0x80, and the data length.
+///
+/// # Arguments
+///
+/// * `payload` - The payload of a frame, without sync bytes, length and checksum.
+///
+/// # Returns
+///
+/// * `Vec<(Code, usize)>` - Each code with the length of its data, in payload
+///   order. The last length is clamped if the payload is truncated.
+pub fn layout(payload: &[u8]) -> Vec<(Code, usize)> {
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < payload.len() {
+        let byte = payload[i];
+        i += 1;
+        let len = match byte {
+            0x55 => 0,
+            0x00..0x80 => 1,
+            _ => {
+                let len = payload.get(i).copied().unwrap_or_default() as usize;
+                i += 1;
+                len
+            }
+        };
+        let len = len.min(payload.len().saturating_sub(i));
+        i += len;
+        entries.push((Code::from(byte), len));
+    }
+    entries
+}
+
+/// Runs a recorded byte stream through the decoder and describes the
+/// structure of each valid frame, see `layout`.
+///
+/// # Arguments
+///
+/// * `bytes` - The recorded bytes, as received from the device.
+///
+/// # Returns
+///
+/// * `Vec<Vec<(Code, usize)>>` - The layout of each valid frame, in order.
+pub fn layouts(bytes: &[u8]) -> Vec<Vec<(Code, usize)>> {
+    let mut decoder = Decoder::new();
+    bytes
+        .iter()
+        .filter_map(|&byte| match decoder.push(byte) {
+            Some(Frame::Packet(_)) => Some(layout(&decoder.payload)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parses a verified payload into a `Packet`, along with the number of
+/// unknown codes encountered. Returns `None` if the payload has an anomaly
+/// and the mode is strict.
+fn decode_payload(payload: &[u8], mode: FramingMode) -> Option<(Packet, usize)> {
+    let mut packet = Packet::default();
+    let mut unknown_codes = 0;
+    let mut i = 0..payload.len();
+    // Logs an anomaly, or rejects the payload in strict mode
+    macro_rules! anomaly {
+        ($level:ident, $($arg:tt)*) => {
+            match mode {
+                FramingMode::Strict => return None,
+                FramingMode::Lenient => diagnostic!($level, $($arg)*),
+            }
+        };
+    }
+    // Reads the next `n` bytes, which may be cut off by the end of the
+    // payload if a code promises more bytes than left
+    macro_rules! take {
+        ($n:expr) => {
+            match payload.get(i.start..i.start + $n) {
+                Some(bytes) => {
+                    i.start += $n;
+                    bytes
+                }
+                None => {
+                    anomaly!(warn, "Payload ends in the middle of a value");
+                    break;
+                }
+            }
+        };
+    }
+    'codes: while let Some(mut idx) = i.next() {
+        // Extended code bytes preceding a code give its extended code level
+        let mut level = 0;
+        while payload[idx] == Code::Extended as u8 {
+            level += 1;
+            match i.next() {
+                Some(next) => idx = next,
+                None => {
+                    anomaly!(warn, "Payload ends after an extended code byte");
+                    break 'codes;
+                }
+            }
+        }
+        // No code is defined at extended code levels above 0 yet
+        let code = match level {
+            0 => Code::from(payload[idx]),
+            _ => Code::Unknown,
+        };
+        match code {
+            // Single-byte codes
+            Code::PoorSignal => packet.poor_signal = Some(take!(1)[0]),
+            Code::Attention => packet.attention = Some(take!(1)[0]),
+            Code::Meditation => packet.meditation = Some(take!(1)[0]),
+            Code::BlinkStrength => packet.blink_strength = Some(take!(1)[0]),
+            Code::Battery => packet.battery = Some(take!(1)[0]),
+            Code::RawWave8 => packet.raw_wave8 = Some(take!(1)[0]),
+            Code::RawMarker => packet.raw_marker = Some(take!(1)[0]),
+
+            // Multi-byte codes
+            Code::RawWave => {
+                let value_length = take!(1)[0];
+                if value_length != 2 {
+                    // Something is wrong with the data, but we don't know what
+                    anomaly!(warn, "Unexpected raw wave length {}", value_length);
+                }
+                let bytes = take!(2);
+                packet.raw_wave = Some(i16::from_be_bytes([bytes[0], bytes[1]]));
+            }
+            Code::AsicEegPower => {
+                let value_length = take!(1)[0];
+                if value_length != 24 {
+                    // Something is wrong with the data, but we don't know what
+                    anomaly!(warn, "Unexpected ASIC EEG power length {}", value_length);
+                }
+                let bytes = take!(24);
+                let mut values = [0; 8];
+                values.iter_mut().zip(bytes.chunks(3)).for_each(|(x, b)| {
+                    *x = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+                });
+                packet.eeg_power = Some(Power {
+                    delta: values[0],
+                    theta: values[1],
+                    low_alpha: values[2],
+                    high_alpha: values[3],
+                    low_beta: values[4],
+                    high_beta: values[5],
+                    low_gamma: values[6],
+                    mid_gamma: values[7],
+                });
+            }
+
+            // Reserved code
+            Code::Sync => {
+                // Sync code encountered
+                anomaly!(debug, "Sync code encountered");
+            }
+            Code::Unknown | Code::Extended => {
+                anomaly!(
+                    debug,
+                    "Unknown code at {} (extended code level {}): 0x{:02X}",
+                    idx,
+                    level,
+                    payload[idx]
+                );
+                unknown_codes += 1;
+                // Skip its value, so the next code is read at the right place
+                let value_length = match payload[idx] {
+                    0x00..0x80 => 1,
+                    _ => take!(1)[0] as usize,
+                };
+                take!(value_length);
+            }
+        }
+    }
+    Some((packet, unknown_codes))
+}
+
+/// Represents a run of bytes in a recording that did not belong to any valid
+/// packet, e.g. noise or a corrupted frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// Offset of the first byte of the run
+    pub offset: usize,
+    /// Number of bytes in the run
+    pub len: usize,
+}
+
+/// Represents a data-quality report of a recorded byte stream, as returned by
+/// `analyze`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionReport {
+    /// Number of valid packets
+    pub total_packets: usize,
+    /// Number of packets with only a raw wave value
+    pub raw_wave_packets: usize,
+    /// Number of packets with eSense values and EEG power spectrum
+    pub eeg_power_packets: usize,
+    /// Number of packets with a blink strength
+    pub blink_packets: usize,
+    /// Number of valid packets that match no `PacketVariant`
+    pub other_packets: usize,
+    /// Number of frames dropped due to a checksum mismatch
+    pub checksum_failures: usize,
+    /// Number of frames dropped due to an invalid payload length
+    pub invalid_lengths: usize,
+    /// Number of times the decoder had to resynchronize, i.e. `gaps.len()`
+    pub resyncs: usize,
+    /// Estimated raw wave samples per second, based on the EEG power spectrum
+    /// being reported once per second. `None` without EEG power packets.
+    pub effective_sample_rate: Option<f64>,
+    /// Runs of bytes between valid packets
+    pub gaps: Vec<Gap>,
+    /// Number of bytes after the last valid packet, e.g. a truncated frame
+    pub trailing_bytes: usize,
+}
+
+/// Runs a recorded byte stream through the decoder and reports on its
+/// data quality. This is the offline counterpart of the live reader.
+///
+/// # Arguments
+///
+/// * `bytes` - The recorded bytes, as received from the device.
+///
+/// # Returns
+///
+/// * `SessionReport` - The data-quality report of the recording.
+pub fn analyze(bytes: &[u8]) -> SessionReport {
+    let mut report = SessionReport::default();
+    let mut decoder = Decoder::new();
+    // End of the last valid frame, and start of the current candidate frame
+    let mut last_end = 0;
+    let mut start = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if matches!(decoder.framing, Framing::Sync { count: 0 } | Framing::Next)
+            && byte == Code::Sync as u8
+        {
+            start = i;
+        }
+        match decoder.push(byte) {
+            Some(Frame::Packet(packet)) => {
+                if start > last_end {
+                    report.gaps.push(Gap {
+                        offset: last_end,
+                        len: start - last_end,
+                    });
+                }
+                last_end = i + 1;
+                report.total_packets += 1;
+                match packet.try_into() {
+                    Ok(PacketVariant::RawWave { .. }) => report.raw_wave_packets += 1,
+                    Ok(PacketVariant::EegPower { .. }) => report.eeg_power_packets += 1,
+                    Ok(PacketVariant::Blink { .. }) => report.blink_packets += 1,
+                    _ => report.other_packets += 1,
+                }
+            }
+            Some(Frame::ChecksumMismatch { .. }) => report.checksum_failures += 1,
+            Some(Frame::InvalidLength(_)) => report.invalid_lengths += 1,
+            // Only produced by strict framing
+            Some(Frame::Rejected) | None => {}
+        }
+    }
+    report.resyncs = report.gaps.len();
+    report.trailing_bytes = bytes.len() - last_end;
+    if report.eeg_power_packets > 0 {
+        report.effective_sample_rate =
+            Some(report.raw_wave_packets as f64 / report.eeg_power_packets as f64);
+    }
+    report
+}
+
+/// Represents what a `DataReader` does once too many consecutive packets
+/// contained unknown codes, which usually means it is connected to the wrong
+/// RFCOMM channel or to a device that is not a NeuroSky device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownCodeAction {
+    /// Discard the buffered bytes and synchronize again with the next frame
+    #[default]
+    Resync,
+    /// Yield an `InvalidData` error and end the stream
+    Error,
+}
+
+/// Represents what a `DataReader` does with a packet whose eSense values
+/// are above `ESENSE_MAX`, which can only come from a corrupted frame that
+/// passed the checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfRangeAction {
+    /// Replace the values with `ESENSE_MAX`
+    Clamp,
+    /// Drop the values, keeping the rest of the packet
+    Drop,
+    /// Yield a `CommError::OutOfRange` error instead of the packet, and keep
+    /// reading
+    Error,
+}
+
+/// Applies the action to the eSense values of the packet that are out of
+/// range.
+fn check_range(packet: &mut Packet, action: OutOfRangeAction) -> Result<(), CommError> {
+    match action {
+        OutOfRangeAction::Clamp => {
+            packet.attention = packet.attention.map(|x| x.min(ESENSE_MAX));
+            packet.meditation = packet.meditation.map(|x| x.min(ESENSE_MAX));
+        }
+        OutOfRangeAction::Drop => {
+            packet.attention = packet.attention.filter(|&x| x <= ESENSE_MAX);
+            packet.meditation = packet.meditation.filter(|&x| x <= ESENSE_MAX);
+        }
+        OutOfRangeAction::Error => packet.validate()?,
+    }
+    Ok(())
+}
+
+/// Rate limiter for repetitive warnings, so a degraded link does not flood
+/// the logs.
+#[derive(Debug)]
+struct Throttle {
+    /// Minimum time between two emitted warnings
+    interval: Duration,
+    /// Instant the last warning was emitted at
+    last: Option<Instant>,
+    /// Number of warnings suppressed since the last emitted one
+    suppressed: u64,
+}
+
+impl Throttle {
+    fn new(interval: Duration) -> Throttle {
+        Throttle {
+            interval,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Whether a warning may be emitted now. Returns the number of warnings
+    /// suppressed since the last emitted one if so.
+    fn allow(&mut self) -> Option<u64> {
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last = Some(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+/// Values dropped from packets whose signal quality is too poor, see
+/// `DataReader::with_power_gate` and `DataReader::with_min_signal_quality`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GateScope {
+    /// The EEG power spectrum only
+    Power,
+    /// The eSense values and the EEG power spectrum
+    EsenseAndPower,
+}
+
+/// Drops the values in `scope` from the packet if its signal quality is
+/// worse than `max_poor_signal`.
+fn gate_signal(packet: &mut Packet, max_poor_signal: u8, scope: GateScope) {
+    if packet.poor_signal.is_some_and(|x| x > max_poor_signal) {
+        packet.eeg_power = None;
+        if scope == GateScope::EsenseAndPower {
+            packet.attention = None;
+            packet.meditation = None;
+        }
+    }
+}
+
+/// Default RFCOMM MTU (bytes). The negotiated MTU is not exposed by the
+/// Linux RFCOMM socket API, but is at least this large.
+pub const RFCOMM_DEFAULT_MTU: usize = 127;
+
+/// Closure invoked with a decoded field.
+type Callback<T> = Box<dyn FnMut(T) + Send>;
+
+/// Closures registered on a `DataReader`, invoked as soon as a field is
+/// decoded.
+#[derive(Default)]
+struct Callbacks {
+    attention: Option<Callback<u8>>,
+    meditation: Option<Callback<u8>>,
+    power: Option<Callback<Power>>,
+    raw_wave: Option<Callback<i16>>,
+}
+
+impl Callbacks {
+    /// Invokes the callback of each field present in the packet.
+    fn dispatch(&mut self, packet: &Packet) {
+        if let (Some(cb), Some(attention)) = (&mut self.attention, packet.attention) {
+            cb(attention);
+        }
+        if let (Some(cb), Some(meditation)) = (&mut self.meditation, packet.meditation) {
+            cb(meditation);
+        }
+        if let (Some(cb), Some(power)) = (&mut self.power, packet.eeg_power) {
+            cb(power);
+        }
+        if let (Some(cb), Some(raw_wave)) = (&mut self.raw_wave, packet.raw_wave) {
+            cb(raw_wave);
+        }
+    }
+}
+
+/// Receiving end of a channel of packets read by a background task, see
+/// `DataReader::spawn` and `DataReader::split`.
+pub type PacketReceiver = Receiver<Result<Packet, Error>>;
+
+/// Represents a data reader that reads and parses data packets from the
+/// NeuroSky device. It reads from any `AsyncRead` stream, an RFCOMM stream by
+/// default, so recorded bytes or an in-memory pipe can stand in for the
+/// device, e.g. in tests.
+pub struct DataReader<R = rfcomm::Stream> {
+    /// The stream to read data from
+    stream: R,
+    /// Framing decoder fed with the bytes read from the stream
+    decoder: Decoder,
+    /// Bytes read from the stream but not decoded yet
+    buffer: Box<[u8]>,
+    /// Position of the next byte to decode in `buffer`
+    pos: usize,
+    /// Number of valid bytes in `buffer`
+    filled: usize,
+    /// Whether the stream has ended
+    ended: bool,
+    /// Instant the reader was constructed at
+    created: Instant,
+    /// Packets received within this period after construction are discarded
+    warmup: Duration,
+    /// Latest values seen on the stream
+    snapshot: Snapshot,
+    /// Start of the current packet rate window
+    rate_window: Instant,
+    /// Number of packets received in the current packet rate window
+    rate_count: u32,
+    /// Intervals between raw wave packets in the current packet rate window
+    raw_intervals: IntervalStats,
+    /// Number of consecutive packets with unknown codes that triggers the
+    /// action, if enabled
+    unknown_code_limit: Option<(usize, UnknownCodeAction)>,
+    /// Number of consecutive packets with unknown codes so far
+    unknown_code_streak: usize,
+    /// Rate limiter for checksum mismatch warnings
+    checksum_log: Throttle,
+    /// Statistics of the session so far
+    stats: SessionStats,
+    /// Counts of the frames decoded since the last reset
+    frame_stats: ReaderStats,
+    /// Closures to invoke as fields are decoded
+    callbacks: Callbacks,
+    /// Action taken on eSense values out of range, if enabled
+    out_of_range: Option<OutOfRangeAction>,
+    /// Transform applied to the EEG power spectrum, if enabled
+    power_transform: Option<PowerTransform>,
+    /// Worst signal quality for which the gated values are kept, and which
+    /// values are gated, if enabled
+    signal_gate: Option<(u8, GateScope)>,
+    /// Classifier run on each packet, if enabled
+    classifier: Option<Classifier>,
+    /// Thresholds for the signal lock
+    lock: LockThresholds,
+    /// Whether corrupted frames are returned as errors instead of skipped
+    frame_errors: bool,
+}
+
+impl<R: AsyncRead + Unpin> DataReader<R> {
+    pub fn new(stream: R) -> DataReader<R> {
+        DataReader {
+            stream,
+            decoder: Decoder::new(),
+            buffer: vec![0; 2 * RFCOMM_DEFAULT_MTU].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            ended: false,
+            created: Instant::now(),
+            warmup: Duration::ZERO,
+            snapshot: Snapshot::default(),
+            rate_window: Instant::now(),
+            rate_count: 0,
+            raw_intervals: IntervalStats::default(),
+            unknown_code_limit: None,
+            unknown_code_streak: 0,
+            checksum_log: Throttle::new(Duration::from_secs(1)),
+            stats: SessionStats::new(),
+            frame_stats: ReaderStats::default(),
+            callbacks: Callbacks::default(),
+            out_of_range: None,
+            power_transform: None,
+            signal_gate: None,
+            classifier: None,
+            lock: LockThresholds::default(),
+            frame_errors: false,
+        }
+    }
+
+    /// Discards packets received during a warmup period after the reader was
+    /// constructed, as the first second or so after connecting is often
+    /// unreliable.
+    ///
+    /// # Arguments
+    ///
+    /// * `warmup` - The warmup period, counted from the construction of the reader.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Takes an action once `limit` consecutive packets contained unknown
+    /// codes. A stream that only yields such packets is almost certainly
+    /// misaligned, or not coming from a NeuroSky device at all. Disabled by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The number of consecutive packets that triggers the action. Must be non-zero.
+    /// * `action` - What to do once the limit is reached.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `limit` is zero.
+    pub fn with_unknown_code_limit(mut self, limit: usize, action: UnknownCodeAction) -> Self {
+        assert!(limit > 0, "Unknown code limit must be non-zero");
+        self.unknown_code_limit = Some((limit, action));
+        self
+    }
+
+    /// Updates how anomalies in the payload of frames with a valid checksum
+    /// are handled. Default: `FramingMode::Lenient`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The framing mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_framing_mode(mut self, mode: FramingMode) -> Self {
+        self.decoder.mode = mode;
+        self
+    }
+
+    /// Updates the largest payload length accepted. Frames declaring a
+    /// longer payload are dropped as soon as their length byte is read,
+    /// instead of reading the payload first. Default: `MAX_PAYLOAD_LEN`.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The largest payload length (bytes), at most `MAX_PAYLOAD_LEN`.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `len` is greater than `MAX_PAYLOAD_LEN`.
+    pub fn with_max_payload_len(mut self, len: usize) -> Self {
+        assert!(
+            len <= MAX_PAYLOAD_LEN,
+            "Payload length must be at most {MAX_PAYLOAD_LEN}"
+        );
+        self.decoder.max_payload = len;
+        self
+    }
+
+    /// Attaches the payload of each frame to the decoded packet, see
+    /// `Packet::raw`, e.g. to report what a device sends in codes that are
+    /// not parsed. Disabled by default, as it allocates for every packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to keep the payloads.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_keep_raw(mut self, enabled: bool) -> Self {
+        self.decoder.keep_raw = enabled;
+        self
+    }
+
+    /// Updates the minimum time between two checksum mismatch warnings. On a
+    /// noisy link, mismatches can happen thousands of times per second, so
+    /// warnings in between are suppressed and counted in the next one.
+    /// `Duration::ZERO` logs every mismatch. Default: 1s.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The minimum time between two warnings.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_log_interval(mut self, interval: Duration) -> Self {
+        self.checksum_log = Throttle::new(interval);
+        self
+    }
+
+    /// Returns corrupted frames as errors instead of skipping them, so
+    /// callers can tell a noisy link from a dropped connection, e.g. to
+    /// reconnect after too many checksum mismatches. The reader keeps
+    /// reading after such an error. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to return `CommError::ChecksumMismatch` and
+    ///   `CommError::InvalidLength` errors.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_frame_errors(mut self, enabled: bool) -> Self {
+        self.frame_errors = enabled;
+        self
+    }
+
+    /// Applies a transform to the EEG power spectrum of each packet, stored
+    /// in `Packet::transformed_power` so the raw values in
+    /// `Packet::eeg_power` remain available. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - The transform applied to each band.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_power_transform(mut self, transform: PowerTransform) -> Self {
+        self.power_transform = Some(transform);
+        self
+    }
+
+    /// Checks that the eSense values of each packet are within
+    /// 0 ~ `ESENSE_MAX`, see `Packet::validate`, so corrupted values do not
+    /// reach dashboards and classifiers. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - What to do with values out of range.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_out_of_range(mut self, action: OutOfRangeAction) -> Self {
+        self.out_of_range = Some(action);
+        self
+    }
+
+    /// Drops the EEG power spectrum of packets whose signal quality is worse
+    /// than `max_poor_signal`, as the ASIC cannot compute a valid spectrum
+    /// during poor contact. The rest of the packet is kept. Replaces the gate
+    /// set by `with_min_signal_quality`, if any. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_poor_signal` - The worst signal quality for which the spectrum is kept (0 ~ 255), 0 meaning a perfect signal.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_power_gate(mut self, max_poor_signal: u8) -> Self {
+        self.signal_gate = Some((max_poor_signal, GateScope::Power));
+        self
+    }
+
+    /// Drops the eSense values and the EEG power spectrum of packets whose
+    /// signal quality is worse than `max_poor_signal`, as they are
+    /// meaningless while the sensors are off the skin, e.g. during setup.
+    /// The rest of the packet, including `poor_signal`, is kept, so the
+    /// contact can still be monitored. Replaces the gate set by
+    /// `with_power_gate`, if any. Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_poor_signal` - The worst signal quality for which the values
+    ///   are kept (0 ~ 255), 0 meaning a perfect signal and 200 no contact.
+    ///   Values equal to it are kept.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_min_signal_quality(mut self, max_poor_signal: u8) -> Self {
+        self.signal_gate = Some((max_poor_signal, GateScope::EsenseAndPower));
+        self
+    }
+
+    /// Runs a classifier on each packet, storing the recognized state in
+    /// `Packet::state`. The classifier sees the packet after the other
+    /// options of the reader were applied, e.g. `with_power_gate`. Disabled by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `classifier` - The closure returning the state of a packet, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_classifier(mut self, classifier: Classifier) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Updates the thresholds for `has_lock`. Default: see `LockThresholds`.
+    ///
+    /// # Arguments
+    ///
+    /// * `thresholds` - The thresholds the values seen on the stream must meet.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_lock_thresholds(mut self, thresholds: LockThresholds) -> Self {
+        self.lock = thresholds;
+        self
+    }
+
+    /// Updates the size of the buffer used to read from the stream. By
+    /// default, the buffer holds two RFCOMM frames of the default MTU. Links
+    /// that negotiated a larger MTU read more efficiently with a buffer that
+    /// is a multiple of it. Bytes buffered but not decoded yet are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the read buffer (bytes). Must be non-zero.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `size` is zero.
+    pub fn with_read_buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "Read buffer size must be non-zero");
+        let pending = &self.buffer[self.pos..self.filled];
+        let mut buffer = vec![0; size.max(pending.len())].into_boxed_slice();
+        buffer[..pending.len()].copy_from_slice(pending);
+        self.filled = pending.len();
+        self.pos = 0;
+        self.buffer = buffer;
+        self
+    }
+
+    /// Returns the size of the buffer used to read from the stream (bytes).
+    pub fn read_buffer_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns where the reader currently is in the framing of the stream,
+    /// e.g. to tell whether a reader that yields no packets receives no data
+    /// at all or cannot find valid frames in it.
+    pub fn state(&self) -> ReaderState {
+        match self.ended {
+            true => ReaderState::Ended,
+            false => self.decoder.state(),
+        }
+    }
+
+    /// Returns a snapshot of the latest values seen on the stream. Fields
+    /// that have not been received yet are `None`.
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot
+    }
+
+    /// Tells whether good data is being received right now, i.e. the
+    /// framing is synced, packets arrive at the expected rate, and the
+    /// electrode contact is good, see `LockThresholds::is_met`. There is no
+    /// lock until the packet rate has been measured over a first second. If
+    /// no packet has been read for over two seconds, the rate so far is used
+    /// instead of the last measurement, so the lock is lost when the stream
+    /// stalls.
+    pub fn has_lock(&self) -> bool {
+        let mut snapshot = self.snapshot;
+        let elapsed = self.rate_window.elapsed();
+        if elapsed > Duration::from_secs(2) {
+            snapshot.packet_rate = self.rate_count as f64 / elapsed.as_secs_f64();
+        }
+        !self.ended && self.lock.is_met(&snapshot)
+    }
+
+    /// Returns the summary of the session since the reader was constructed.
+    /// Packets discarded during the warmup period are not counted.
+    pub fn summary(&self) -> SessionSummary {
+        self.stats.summary()
+    }
+
+    /// Returns the counts of the frames decoded since the reader was
+    /// constructed, or since the last `reset_stats`, e.g. to alert when the
+    /// rate of corrupted frames spikes.
+    pub fn stats(&self) -> ReaderStats {
+        self.frame_stats
+    }
+
+    /// Resets the counts returned by `stats`, e.g. to measure the link
+    /// quality over consecutive windows. The session summary is not reset.
+    ///
+    /// # Returns
+    ///
+    /// * `ReaderStats` - The counts before the reset.
+    pub fn reset_stats(&mut self) -> ReaderStats {
+        std::mem::take(&mut self.frame_stats)
+    }
+
+    /// Updates the signal strength reported in the snapshot. The reader has
+    /// no access to the Bluetooth device, so this is left to the caller,
+    /// e.g. from `device::RssiMonitor::watch`.
+    ///
+    /// # Arguments
+    ///
+    /// * `rssi` - The signal strength of the connection (dBm).
+    pub fn set_rssi(&mut self, rssi: Option<i16>) {
+        self.snapshot.rssi = rssi;
+    }
+
+    /// Returns a writer that sends configuration commands to the device over
+    /// the same stream the packets are read from.
+    pub fn writer(&mut self) -> DeviceWriter<&mut R>
+    where
+        R: AsyncWrite,
+    {
+        DeviceWriter::new(&mut self.stream)
+    }
+
+    /// Sends a configuration command to the device, e.g. to force a known
+    /// output mode at the start of a session. See `command` for the
+    /// supported command bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the stream fails.
+    pub async fn send_command(&mut self, command: Command) -> Result<(), Error>
+    where
+        R: AsyncWrite,
+    {
+        self.writer().send(command).await
+    }
+
+    /// Consumes the reader and shuts the underlying stream down, flushing
+    /// pending writes first, so the connection is closed deterministically
+    /// rather than whenever the reader is dropped. The device can be
+    /// connected to again right after. Bytes read from the stream but not
+    /// decoded yet are lost.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if flushing or shutting the stream
+    /// down fails. The stream is closed anyway once dropped.
+    pub async fn close(mut self) -> Result<(), CommError>
+    where
+        R: AsyncWrite,
+    {
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Consumes the reader, returning the underlying stream. Bytes read from
+    /// the stream but not decoded yet are lost.
+    pub fn into_inner(self) -> R {
+        self.stream
+    }
+
+    /// Moves the reader into a background task which reads packets into a
+    /// bounded channel, so a slow consumer does not stall the reader. The
+    /// task stops once the stream ends, or as soon as the receiver is dropped
+    /// or aborted, which closes the connection so the device can be
+    /// connected to again.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of packets queued. Must be non-zero.
+    /// * `policy` - What to do with new packets when the channel is full.
+    ///
+    /// # Returns
+    ///
+    /// * `Receiver` - The receiving end of the channel, following the same
+    ///   contract as the `Stream` implementation of the reader.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is zero, or if called outside of a
+    /// tokio runtime.
+    pub fn spawn(mut self, capacity: usize, policy: OverflowPolicy) -> PacketReceiver
+    where
+        R: Send + 'static,
+    {
+        let (sender, mut receiver) = channel(capacity, policy);
+        let task = tokio::spawn(async move {
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut self).poll_next(cx)).await {
+                if sender.send(item.map_err(Error::from)).await.is_err() {
+                    // The receiver has been dropped
+                    break;
+                }
+            }
+        });
+        receiver.set_task(task.abort_handle());
+        receiver
+    }
+
+    /// Moves the reader into a background task shared by any number of
+    /// consumers, so several consumers receive the same packets from a
+    /// single connection. The task stops once the stream ends, or once all
+    /// receivers and `Subscriptions` handles are dropped.
+    ///
+    /// # Returns
+    ///
+    /// * `Subscriptions` - The handle to add consumers, each receiving every
+    ///   item following the same contract as the `Stream` implementation of
+    ///   the reader. Errors are copied with their kind and message.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if called outside of a tokio runtime.
+    pub fn spawn_shared(mut self) -> Subscriptions<Result<Packet, Error>>
+    where
+        R: Send + 'static,
+    {
+        let (mut fanout, subscriptions) = fanout();
+        tokio::spawn(async move {
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut self).poll_next(cx)).await {
+                let item = item.map_err(Error::from);
+                let make = || match &item {
+                    Ok(packet) => Ok(packet.clone()),
+                    Err(e) => Err(Error::new(e.kind(), e.to_string())),
+                };
+                if !fanout.send_with(make).await {
+                    // All consumers have been dropped
+                    break;
+                }
+            }
+        });
+        subscriptions
+    }
+
+    /// Moves the reader into a background task which routes raw wave packets
+    /// and the other packets into two bounded channels, so each kind goes to
+    /// its own consumer, e.g. a signal buffer and a UI gauge, while frames
+    /// are read once. Packets with a raw wave value, full or reduced
+    /// precision, or a raw marker go to the first channel; packets with signal quality,
+    /// eSense, blink strength, battery, or EEG power values go to the second
+    /// one. A packet with both goes to both. Errors are copied to both
+    /// channels with their kind and message. The task stops once the stream
+    /// ends, or once both receivers are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of packets queued in each channel.
+    ///   Must be non-zero.
+    /// * `policy` - What to do with new packets when a channel is full. With
+    ///   `OverflowPolicy::Block`, a stalled consumer stalls the other one too.
+    ///
+    /// # Returns
+    ///
+    /// * `(PacketReceiver, PacketReceiver)` - The receiving ends of the raw wave and the
+    ///   power channels, following the same contract as the `Stream`
+    ///   implementation of the reader.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is zero, or if called outside of a
+    /// tokio runtime.
+    pub fn split(
+        mut self,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> (PacketReceiver, PacketReceiver)
+    where
+        R: Send + 'static,
+    {
+        let (raw_sender, raw) = channel(capacity, policy);
+        let (power_sender, power) = channel(capacity, policy);
+        tokio::spawn(async move {
+            let (mut raw_open, mut power_open) = (true, true);
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut self).poll_next(cx)).await {
+                match item.map_err(Error::from) {
+                    Ok(packet) => {
+                        if packet.raw_wave.is_some()
+                            || packet.raw_wave8.is_some()
+                            || packet.raw_marker.is_some()
+                        {
+                            raw_open = raw_sender.send(Ok(packet.clone())).await.is_ok();
+                        }
+                        if packet.poor_signal.is_some()
+                            || packet.attention.is_some()
+                            || packet.meditation.is_some()
+                            || packet.blink_strength.is_some()
+                            || packet.battery.is_some()
+                            || packet.eeg_power.is_some()
+                        {
+                            power_open = power_sender.send(Ok(packet)).await.is_ok();
+                        }
+                    }
+                    Err(e) => {
+                        let copy = Error::new(e.kind(), e.to_string());
+                        raw_open = raw_sender.send(Err(copy)).await.is_ok();
+                        power_open = power_sender.send(Err(e)).await.is_ok();
+                    }
+                }
+                if !raw_open && !power_open {
+                    // Both receivers have been dropped
+                    break;
+                }
+            }
+        });
+        (raw, power)
+    }
+
+    /// Moves the reader into a background task broadcasting packets to any
+    /// number of subscribers. Packets are shared behind an `Arc`, so they are
+    /// not copied for each subscriber. More subscribers are added with
+    /// `broadcast::Receiver::resubscribe`.
+    ///
+    /// A subscriber falling more than `capacity` packets behind skips the
+    /// oldest ones, and is told how many with `RecvError::Lagged`. The task
+    /// stops once all subscribers are dropped, or once the stream ends, in
+    /// which case subscribers receive `RecvError::Closed` after the remaining
+    /// packets. Errors are logged, not broadcast: an error ending the stream,
+    /// e.g. a dropped connection, stops the task, while the errors returned
+    /// without ending it, see `with_frame_errors` and `with_out_of_range`, are
+    /// skipped.
+    ///
+    /// Use `subscribe` for consumers that only need the latest packets, e.g.
+    /// a live display, as a slow subscriber skips packets instead of holding
+    /// the others back. Use `spawn_shared` for consumers that need every
+    /// item, errors included, e.g. a recorder, each with its own bounded
+    /// channel and overflow policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of packets retained for subscribers
+    ///   behind. Must be non-zero.
+    ///
+    /// # Returns
+    ///
+    /// * `broadcast::Receiver<Arc<Packet>>` - The first subscriber.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `capacity` is zero, or if called outside of a
+    /// tokio runtime.
+    pub fn subscribe(mut self, capacity: usize) -> broadcast::Receiver<Arc<Packet>>
+    where
+        R: Send + 'static,
+    {
+        let (sender, receiver) = broadcast::channel(capacity);
+        tokio::spawn(async move {
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut self).poll_next(cx)).await {
+                match item {
+                    Ok(packet) => {
+                        if sender.send(Arc::new(packet)).is_err() {
+                            // All subscribers have been dropped
+                            break;
+                        }
+                    }
+                    Err(e) => diagnostic!(warn, "Packet not broadcast: {}", e),
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Registers a closure invoked with each attention value, as soon as it
+    /// is decoded. Callbacks run while the reader is polled, so the packets
+    /// must still be consumed, e.g. by `spawn` or a loop over the stream.
+    /// Replaces any closure registered before.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The closure, given the attention value (0 ~ 100).
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn on_attention(mut self, cb: impl FnMut(u8) + Send + 'static) -> Self {
+        self.callbacks.attention = Some(Box::new(cb));
+        self
+    }
+
+    /// Registers a closure invoked with each meditation value, see
+    /// `on_attention`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The closure, given the meditation value (0 ~ 100).
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn on_meditation(mut self, cb: impl FnMut(u8) + Send + 'static) -> Self {
+        self.callbacks.meditation = Some(Box::new(cb));
+        self
+    }
+
+    /// Registers a closure invoked with each EEG power spectrum, see
+    /// `on_attention`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The closure, given the EEG power spectrum.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn on_power(mut self, cb: impl FnMut(Power) + Send + 'static) -> Self {
+        self.callbacks.power = Some(Box::new(cb));
+        self
+    }
+
+    /// Registers a closure invoked with each raw wave value, see
+    /// `on_attention`. It runs about 512 times per second, so it should
+    /// return quickly.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The closure, given the raw wave value.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn on_raw_wave(mut self, cb: impl FnMut(i16) + Send + 'static) -> Self {
+        self.callbacks.raw_wave = Some(Box::new(cb));
+        self
+    }
+
+    /// Counts a frame output by the decoder in the frame statistics.
+    fn count_frame(&mut self, frame: Option<&Frame>) {
+        let stats = &mut self.frame_stats;
+        match frame {
+            Some(Frame::Packet(_)) => {
+                stats.packets_ok += 1;
+                stats.unknown_codes += self.decoder.unknown_codes as u64;
+            }
+            Some(Frame::ChecksumMismatch { .. }) => stats.checksum_errors += 1,
+            Some(Frame::InvalidLength(_)) => stats.invalid_lengths += 1,
+            Some(Frame::Rejected) => stats.rejected += 1,
+            None => {}
+        }
+    }
+
+    /// Updates the streak of consecutive packets with unknown codes after a
+    /// valid frame, returning the action to take if the limit is reached.
+    fn track_unknown_codes(&mut self) -> Option<UnknownCodeAction> {
+        if self.decoder.unknown_codes == 0 {
+            self.unknown_code_streak = 0;
+            return None;
+        }
+        self.unknown_code_streak += 1;
+        match self.unknown_code_limit {
+            Some((limit, action)) if self.unknown_code_streak >= limit => {
+                self.unknown_code_streak = 0;
+                Some(action)
+            }
+            _ => None,
+        }
+    }
+
+    /// Merges a freshly parsed packet into the snapshot and updates the
+    /// packet rate and jitter.
+    fn update_snapshot(&mut self, packet: &Packet) {
+        let snapshot = &mut self.snapshot;
+        snapshot.poor_signal = packet.poor_signal.or(snapshot.poor_signal);
+        snapshot.attention = packet.attention.or(snapshot.attention);
+        snapshot.meditation = packet.meditation.or(snapshot.meditation);
+        snapshot.eeg_power = packet.eeg_power.or(snapshot.eeg_power);
+        snapshot.battery = packet.battery.or(snapshot.battery);
+        snapshot.synced = true;
+        self.stats.push(packet);
+
+        let now = Instant::now();
+        if packet.raw_wave.is_some() {
+            self.raw_intervals.record(now);
+        }
+        self.rate_count += 1;
+        let elapsed = now.duration_since(self.rate_window);
+        if elapsed >= Duration::from_secs(1) {
+            snapshot.packet_rate = self.rate_count as f64 / elapsed.as_secs_f64();
+            snapshot.jitter = self.raw_intervals.std_dev();
+            self.rate_window = now;
+            self.rate_count = 0;
+            self.raw_intervals.reset();
+            #[cfg(feature = "metrics")]
+            {
+                metrics::gauge!("cerebrust.packet.rate").set(snapshot.packet_rate);
+                if let Some(jitter) = snapshot.jitter {
+                    metrics::gauge!("cerebrust.packet.jitter").set(jitter.as_secs_f64());
+                }
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("cerebrust.packets.total").increment(1);
+            if let Some(poor_signal) = packet.poor_signal {
+                metrics::gauge!("cerebrust.signal.poor").set(poor_signal);
+            }
+        }
+    }
+
+    /// Reads the next data packet from the stream. It synchronizes with the
+    /// NeuroSky device, reads the packet length, and then reads the payload
+    /// and checksum. It verifies the checksum and parses the payload into a
+    /// `Packet` struct. If the packet is corrupted or invalid, it retries
+    /// reading the packet until a valid one is received.
+    ///
+    /// Unlike the `Stream` implementation, the end of the stream is reported
+    /// as a `CommError::Io` error of kind `UnexpectedEof`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from the stream fails,
+    /// if the stream ends, or if a frame is corrupted and
+    /// `with_frame_errors` is enabled. See `CommError`.
+    pub async fn poll_next(&mut self) -> Result<Packet, CommError> {
+        match poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await {
+            Some(result) => result,
+            None => Err(Error::new(ErrorKind::UnexpectedEof, "Stream has ended").into()),
+        }
+    }
+
+    /// Reads the next data packet that converts into a `PacketVariant`, see
+    /// `poll_next`. Packets matching no variant are skipped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading from the stream fails,
+    /// if the stream ends, or if a frame is corrupted and
+    /// `with_frame_errors` is enabled. See `CommError`.
+    pub async fn poll_next_variant(&mut self) -> Result<PacketVariant, CommError> {
+        loop {
+            match self.poll_next().await?.try_into() {
+                Err(CommError::UnknownVariant) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns a stream of the packets converted into `PacketVariant`s,
+    /// following the contract of the `Stream` implementation. Packets
+    /// matching no variant are skipped.
+    pub fn variants(&mut self) -> impl Stream<Item = Result<PacketVariant, CommError>> + '_ {
+        StreamExt::filter_map(self, |result| {
+            ready(match result.and_then(Packet::try_into) {
+                Err(CommError::UnknownVariant) => None,
+                result => Some(result),
+            })
+        })
+    }
+
+    /// Reads a fixed number of packets, e.g. to record a baseline.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of packets to read.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Packet>, PartialRead>` - The `n` packets, in arrival
+    ///   order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading a packet fails, see
+    /// `poll_next`. The error holds the packets read before it.
+    pub async fn read_packets(&mut self, n: usize) -> Result<Vec<Packet>, PartialRead> {
+        let mut packets = Vec::with_capacity(n);
+        while packets.len() < n {
+            match self.poll_next().await {
+                Ok(packet) => packets.push(packet),
+                Err(error) => return Err(PartialRead { packets, error }),
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Reads all packets received within a time window, e.g. for a
+    /// fixed-length recording. Packets that are only partially read when the
+    /// window closes are not lost, and are returned by the next read.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The length of the window, starting now.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Packet>, PartialRead>` - The packets received, in
+    ///   arrival order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading a packet fails before
+    /// the window closes, see `poll_next`. The error holds the packets read
+    /// before it.
+    pub async fn read_for(&mut self, duration: Duration) -> Result<Vec<Packet>, PartialRead> {
+        let deadline = tokio::time::Instant::now() + duration;
+        let mut packets = Vec::new();
+        loop {
+            match tokio::time::timeout_at(deadline, self.poll_next()).await {
+                Ok(Ok(packet)) => packets.push(packet),
+                Ok(Err(error)) => return Err(PartialRead { packets, error }),
+                Err(_) => return Ok(packets),
+            }
+        }
+    }
+
+    /// Collects packets until one satisfies the predicate or the timeout
+    /// expires, whichever comes first. Packets that are only partially read
+    /// when the timeout expires are not lost, and are returned by the next
+    /// read.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred` - Called on each packet; returns `true` to stop collecting.
+    /// * `timeout` - The maximum time to wait for the predicate to be satisfied.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Collected, CommError>` - The collected packets, including the
+    ///   one that satisfied the predicate, if any.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if reading a packet fails, see
+    /// `poll_next`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # async fn example(reader: &mut cerebrust::DataReader) -> std::io::Result<()> {
+    /// // Wait for meditation to exceed 80
+    /// let collected = reader
+    ///     .read_until(|p| p.meditation > Some(80), Duration::from_secs(60))
+    ///     .await?;
+    /// // Collect 100 packets
+    /// let mut count = 0;
+    /// let collected = reader
+    ///     .read_until(|_| { count += 1; count == 100 }, Duration::from_secs(5))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read_until<F: FnMut(&Packet) -> bool>(
+        &mut self,
+        mut pred: F,
+        timeout: Duration,
+    ) -> Result<Collected, CommError> {
+        let mut collected = Collected::default();
+        let result = tokio::time::timeout(timeout, async {
+            loop {
+                let packet = self.poll_next().await?;
+                let matched = pred(&packet);
+                collected.packets.push(packet);
+                if matched {
+                    return Ok::<_, CommError>(());
+                }
+            }
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => collected.matched = true,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {}
+        }
+        Ok(collected)
+    }
+}
+
+impl DataReader<rfcomm::Stream> {
+    /// Returns the local address of the underlying stream, i.e. the adapter
+    /// and RFCOMM channel the connection was made from.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the socket fails.
+    pub fn local_addr(&self) -> Result<rfcomm::SocketAddr, Error> {
+        self.stream.as_ref().local_addr()
+    }
+
+    /// Returns the peer address of the underlying stream, i.e. the device
+    /// and RFCOMM channel the connection was made to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the socket fails.
+    pub fn peer_addr(&self) -> Result<rfcomm::SocketAddr, Error> {
+        self.stream.peer_addr()
+    }
+
+    /// Returns the security level of the underlying stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the socket fails.
+    pub fn security(&self) -> Result<rfcomm::Security, Error> {
+        self.stream.as_ref().security()
+    }
+
+    /// Returns the size of the socket receive buffer of the underlying
+    /// stream (bytes), i.e. how much data the kernel queues before the
+    /// device is throttled.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the socket fails.
+    pub fn recv_buffer(&self) -> Result<usize, Error> {
+        Ok(self.stream.as_ref().recv_buffer()? as usize)
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for DataReader<R> {
+    type Item = Result<Packet, CommError>;
+
+    /// Polls the next valid packet from the stream. Corrupted frames are
+    /// skipped. The stream yields `None` once the underlying stream reaches
+    /// end-of-file between frames, and a `TruncatedPayload` error if it ends
+    /// in the middle of a frame. Read errors are yielded as `Err` items. After
+    /// the end of the stream or an error, the stream yields `None`, except
+    /// for corrupted frames returned with `DataReader::with_frame_errors`,
+    /// and for values out of range returned with
+    /// `DataReader::with_out_of_range`.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.ended {
+            while this.pos < this.filled {
+                let byte = this.buffer[this.pos];
+                this.pos += 1;
+                let frame = this.decoder.push(byte);
+                this.count_frame(frame.as_ref());
+                match frame {
+                    Some(Frame::Packet(_)) if this.created.elapsed() < this.warmup => {}
+                    Some(Frame::Packet(mut packet)) => match this.track_unknown_codes() {
+                        Some(UnknownCodeAction::Resync) => {
+                            diagnostic!(
+                                warn,
+                                "Too many packets with unknown codes, resynchronizing"
+                            );
+                            this.snapshot.synced = false;
+                            this.decoder.reset();
+                            this.pos = this.filled;
+                        }
+                        Some(UnknownCodeAction::Error) => {
+                            this.ended = true;
+                            return Poll::Ready(Some(Err(Error::new(
+                                ErrorKind::InvalidData,
+                                "Too many packets with unknown codes, check the RFCOMM channel and the target device",
+                            )
+                            .into())));
+                        }
+                        None => {
+                            packet.received_at = Some(Instant::now());
+                            packet.received_time = Some(SystemTime::now());
+                            if let Some(action) = this.out_of_range
+                                && let Err(e) = check_range(&mut packet, action)
+                            {
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                            if let Some((max_poor_signal, scope)) = this.signal_gate {
+                                gate_signal(&mut packet, max_poor_signal, scope);
+                            }
+                            if let Some(transform) = this.power_transform {
+                                packet.transformed_power =
+                                    packet.eeg_power.map(|power| transform.apply(&power));
+                            }
+                            if let Some(classifier) = &mut this.classifier {
+                                packet.state = classifier(&packet);
+                            }
+                            this.update_snapshot(&packet);
+                            this.callbacks.dispatch(&packet);
+                            return Poll::Ready(Some(Ok(packet)));
+                        }
+                    },
+                    Some(Frame::ChecksumMismatch { expected, got }) => {
+                        this.snapshot.synced = false;
+                        this.stats.push_checksum_failure();
+                        #[cfg(feature = "metrics")]
+                        metrics::counter!("cerebrust.checksum.failures").increment(1);
+                        if this.frame_errors {
+                            return Poll::Ready(Some(Err(CommError::ChecksumMismatch {
+                                expected,
+                                got,
+                            })));
+                        }
+                        if let Some(suppressed) = this.checksum_log.allow() {
+                            diagnostic!(
+                                warn,
+                                "Checksum mismatch: 0b{:08b} (Expected) != 0b{:08b} (Got)",
+                                expected,
+                                got
+                            );
+                            if suppressed > 0 {
+                                diagnostic!(
+                                    warn,
+                                    "{} similar checksum mismatches suppressed",
+                                    suppressed
+                                );
+                            }
+                        }
+                    }
+                    Some(Frame::InvalidLength(len)) => {
+                        this.snapshot.synced = false;
+                        if this.frame_errors {
+                            return Poll::Ready(Some(Err(CommError::InvalidLength(len))));
+                        }
+                    }
+                    Some(Frame::Rejected) => this.snapshot.synced = false,
+                    None => {}
+                }
+            }
+            let mut buf = ReadBuf::new(&mut this.buffer);
+            match Pin::new(&mut this.stream).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    this.ended = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                Poll::Ready(Ok(())) => {
+                    this.pos = 0;
+                    this.filled = buf.filled().len();
+                    if this.filled == 0 {
+                        this.ended = true;
+                        if !this.decoder.is_idle() {
+                            this.frame_stats.truncated += 1;
+                            return Poll::Ready(Some(Err(CommError::TruncatedPayload)));
+                        }
+                    }
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+impl<R: AsyncRead + Unpin> Iterator for DataReader<R> {
+    type Item = Result<Packet, CommError>;
+
+    /// Polls the next packet from the stream. It blocks until a valid packet
+    /// is received. Follows the same contract as the `Stream` implementation:
+    /// errors are yielded as `Err` items, and `None` marks the end of the
+    /// stream.
+    ///
+    /// The calling thread is blocked without driving any runtime, so an
+    /// RFCOMM stream only makes progress while the runtime it was created in
+    /// keeps running on other threads. Calling it from within a task of that
+    /// runtime can deadlock. From synchronous code, prefer
+    /// `blocking::BlockingDataReader`, which owns its runtime.
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_frame() {
+        let packets = [
+            Packet {
+                raw_wave: Some(-2),
+                ..Default::default()
+            },
+            Packet {
+                poor_signal: Some(0),
+                attention: Some(50),
+                meditation: Some(40),
+                blink_strength: Some(90),
+                battery: Some(100),
+                raw_wave8: Some(0x80),
+                raw_marker: Some(3),
+                eeg_power: Some(Power {
+                    delta: 0xFF_FFFF,
+                    mid_gamma: 12,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ];
+        let mut decoder = Decoder::new();
+        decoder.mode = FramingMode::Strict;
+        for packet in packets {
+            let frames: Vec<_> = packet
+                .to_frame()
+                .into_iter()
+                .filter_map(|b| decoder.push(b))
+                .collect();
+            assert!(matches!(frames[..], [Frame::Packet(ref p)] if *p == packet));
+        }
+    }
+
+    #[test]
+    fn test_raw_wave_sign() {
+        let cases: [([u8; 2], i16); 7] = [
+            ([0x00, 0x00], 0),
+            ([0x00, 0x01], 1),
+            ([0x7F, 0xFF], i16::MAX),
+            ([0xFF, 0xFF], -1),
+            ([0x80, 0x00], i16::MIN),
+            ([0xFF, 0x00], -256),
+            // Sync bytes in the payload must not restart the frame
+            ([0xAA, 0xAA], -21846),
+        ];
+        for strict in [false, true] {
+            let mut decoder = Decoder::new();
+            if strict {
+                decoder.mode = FramingMode::Strict;
+            }
+            for (bytes, expected) in cases {
+                let frames: Vec<_> = frame(&[0x80, 0x02, bytes[0], bytes[1]])
+                    .into_iter()
+                    .filter_map(|b| decoder.push(b))
+                    .collect();
+                match &frames[..] {
+                    [Frame::Packet(packet)] => assert_eq!(packet.raw_wave, Some(expected)),
+                    _ => panic!("{bytes:02X?} was not decoded"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut bytes = vec![0x12, 0x34];
+        bytes.extend(frame(&[0x80, 0x02, 0x00, 0x10]));
+        bytes.extend(frame(&[0x80, 0x02, 0x00, 0x20]));
+        let mut corrupted = frame(&[0x80, 0x02, 0x00, 0x30]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        bytes.extend(corrupted);
+        let mut power = vec![0x02, 0x00, 0x04, 0x32, 0x05, 0x28, 0x83, 0x18];
+        power.extend([0; 24]);
+        bytes.extend(frame(&power));
+        bytes.extend([0xAA, 0xAA, 0x04, 0x80]);
+
+        let report = analyze(&bytes);
+        assert_eq!(report.total_packets, 3);
+        assert_eq!(report.raw_wave_packets, 2);
+        assert_eq!(report.eeg_power_packets, 1);
+        assert_eq!(report.checksum_failures, 1);
+        assert_eq!(
+            report.gaps,
+            vec![Gap { offset: 0, len: 2 }, Gap { offset: 18, len: 8 }]
+        );
+        assert_eq!(report.resyncs, 2);
+        assert_eq!(report.trailing_bytes, 4);
+        assert_eq!(report.effective_sample_rate, Some(2.0));
+    }
+
+    #[test]
+    fn test_power_map() {
+        let power = Power {
+            delta: 1,
+            theta: 2,
+            mid_gamma: 8,
+            ..Default::default()
+        };
+        let map = power.to_map();
+        assert_eq!(map.len(), 8);
+        assert_eq!(map.keys().next(), Some(&Band::Delta));
+        assert_eq!(map[&Band::MidGamma], 8);
+        assert_eq!(Power::from_map(&map), power);
+        let sparse = BTreeMap::from([(Band::Theta, 2)]);
+        assert_eq!(Power::from_map(&sparse).theta, 2);
+        assert_eq!(Power::from_map(&sparse).total(), 2);
+        assert_eq!(power.as_array(), [1, 2, 0, 0, 0, 0, 0, 8]);
+        assert_eq!(power.bands()[2], ("low_alpha", 0));
+        assert_eq!(power.bands()[7], ("mid_gamma", 8));
+    }
+
+    #[test]
+    fn test_display() {
+        let power = Power {
+            delta: 1,
+            mid_gamma: 8,
+            ..Default::default()
+        };
+        assert_eq!(
+            power.to_string(),
+            "delta=1 theta=0 low_alpha=0 high_alpha=0 low_beta=0 high_beta=0 low_gamma=0 mid_gamma=8"
+        );
+        let packet = Packet {
+            poor_signal: Some(0),
+            attention: Some(57),
+            meditation: Some(42),
+            ..Default::default()
+        };
+        assert_eq!(packet.to_string(), "sig=0 att=57 med=42");
+        let packet = Packet {
+            eeg_power: Some(power),
+            ..packet
+        };
+        assert_eq!(packet.to_string(), format!("sig=0 att=57 med=42 {}", power));
+        assert_eq!(Packet::default().to_string(), "(empty)");
+    }
+
+    #[test]
+    fn test_band_groups() {
+        let power = Power {
+            delta: 1,
+            theta: 2,
+            low_alpha: u32::MAX,
+            high_alpha: 1,
+            low_beta: 5,
+            high_beta: 6,
+            low_gamma: 7,
+            mid_gamma: 8,
+        };
+        assert_eq!(power.delta(), 1);
+        assert_eq!(power.theta(), 2);
+        assert_eq!(power.alpha(), u32::MAX as u64 + 1);
+        assert_eq!(power.beta(), 11);
+        assert_eq!(power.gamma(), 15);
+        assert_eq!(
+            power.delta() + power.theta() + power.alpha() + power.beta() + power.gamma(),
+            power.total()
+        );
+    }
+
+    #[test]
+    fn test_power_transform() {
+        let power = Power {
+            delta: 100,
+            theta: 0,
+            ..Default::default()
+        };
+        assert_eq!(PowerTransform::Identity.apply(&power)[0], 100.0);
+        assert_eq!(PowerTransform::Sqrt.apply(&power)[0], 10.0);
+        assert_eq!(PowerTransform::Log.apply(&power)[1], 0.0);
+        assert_eq!(
+            PowerTransform::Custom(|x| x as f64 / 2.0).apply(&power)[0],
+            50.0
+        );
+    }
+
+    #[test]
+    fn test_packet_variant() {
+        let eeg_power = Power {
+            delta: 3,
+            theta: 1,
+            ..Default::default()
+        };
+        let packet = Packet {
+            poor_signal: Some(0),
+            attention: Some(50),
+            meditation: Some(40),
+            eeg_power: Some(eeg_power),
+            ..Default::default()
+        };
+        let variant: PacketVariant = packet.try_into().unwrap();
+        assert_eq!(
+            variant,
+            PacketVariant::EegPower {
+                poor_signal: 0,
+                attention: 50,
+                meditation: 40,
+                eeg_power,
+            }
+        );
+        let mut relative_power = [0.0; 8];
+        relative_power[..2].copy_from_slice(&[0.75, 0.25]);
+        assert_eq!(
+            variant.into_relative(),
+            PacketVariant::RelativePower {
+                poor_signal: 0,
+                attention: 50,
+                meditation: 40,
+                relative_power,
+            }
+        );
+        let raw = Packet {
+            raw_wave: Some(-7),
+            ..Default::default()
+        };
+        let variant: PacketVariant = raw.try_into().unwrap();
+        assert_eq!(variant, PacketVariant::RawWave { raw_wave: -7 });
+        let battery = Packet {
+            battery: Some(100),
+            ..Default::default()
+        };
+        let result: Result<PacketVariant, _> = battery.try_into();
+        assert!(matches!(result, Err(CommError::UnknownVariant)));
+        assert_eq!(Power::default().relative(), [0.0; 8]);
+    }
+
+    #[test]
+    fn test_power_gate() {
+        let packet = |poor_signal| Packet {
+            poor_signal,
+            attention: Some(50),
+            eeg_power: Some(Power::default()),
+            ..Default::default()
+        };
+        for (poor_signal, kept) in [
+            (Some(0), true),
+            (Some(25), true),
+            (Some(26), false),
+            (None, true),
+        ] {
+            let mut gated = packet(poor_signal);
+            gate_signal(&mut gated, 25, GateScope::Power);
+            assert_eq!(gated.eeg_power.is_some(), kept);
+            assert_eq!(gated.attention, Some(50));
+
+            let mut gated = packet(poor_signal);
+            gate_signal(&mut gated, 25, GateScope::EsenseAndPower);
+            assert_eq!(gated.eeg_power.is_some(), kept);
+            assert_eq!(gated.attention.is_some(), kept);
+            assert_eq!(gated.poor_signal, poor_signal);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let mut corrupted = frame(&[0x04, 0x37]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let bytes = [frame(&[0x04, 0x32]), corrupted, frame(&[0x04, 0x3C])].concat();
+
+        let mut receiver = DataReader::new(std::io::Cursor::new(bytes))
+            .with_frame_errors(true)
+            .subscribe(8);
+        // The checksum mismatch is skipped, and the task keeps reading
+        assert_eq!(receiver.recv().await.unwrap().attention, Some(50));
+        assert_eq!(receiver.recv().await.unwrap().attention, Some(60));
+        assert!(matches!(
+            receiver.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_until() {
+        let bytes = [
+            frame(&[0x04, 0x32]),
+            frame(&[0x04, 0x3C]),
+            frame(&[0x04, 0x46]),
+        ]
+        .concat();
+        let mut reader = DataReader::new(&bytes[..]);
+        let collected = reader
+            .read_until(|p| p.attention == Some(60), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(collected.matched);
+        assert_eq!(collected.packets.len(), 2);
+        let e = reader
+            .read_until(|_| false, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, CommError::Io(ref e) if e.kind() == ErrorKind::UnexpectedEof));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let mut power = vec![0x83, 0x18];
+        power.extend((1..=8).flat_map(|x| [0x00, 0x00, x]));
+        let mut corrupted = frame(&[0x04, 0x37]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let bytes = [
+            frame(&[0x02, 0x1A, 0x04, 0x32]),
+            frame(&power),
+            corrupted,
+            frame(&[0x02, 0x00]),
+        ]
+        .concat();
+
+        let mut reader = DataReader::new(&bytes[..]).with_frame_errors(true);
+        assert_eq!(reader.snapshot(), Snapshot::default());
+        reader.poll_next().await.unwrap();
+        reader.poll_next().await.unwrap();
+        let snapshot = reader.snapshot();
+        assert_eq!(snapshot.poor_signal, Some(26));
+        // Values missing from the latest packet are kept from earlier ones
+        assert_eq!(snapshot.attention, Some(50));
+        let power = snapshot.eeg_power.unwrap();
+        assert_eq!((power.delta, power.mid_gamma), (1, 8));
+        assert!(snapshot.synced);
+
+        assert!(reader.poll_next().await.is_err());
+        assert!(!reader.snapshot().synced);
+        reader.poll_next().await.unwrap();
+        let snapshot = reader.snapshot();
+        assert_eq!(snapshot.poor_signal, Some(0));
+        assert_eq!(snapshot.attention, Some(50));
+        assert!(snapshot.synced);
+    }
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let bytes = [frame(&[0x02, 0x00, 0x04, 0x32]), frame(&[0x04, 0x3C])].concat();
+
+        let mut reader = DataReader::new(&bytes[..]).with_warmup(Duration::from_secs(60));
+        // Both packets arrive during the warmup period, so none is yielded
+        assert!(futures::StreamExt::next(&mut reader).await.is_none());
+        let snapshot = reader.snapshot();
+        assert_eq!(snapshot.poor_signal, None);
+        assert_eq!(snapshot.attention, None);
+        assert!(!snapshot.synced);
+        assert_eq!(reader.summary().total_packets, 0);
+        // Frames are still counted, to measure the quality of the link
+        assert_eq!(reader.stats().packets_ok, 2);
+
+        let mut reader = DataReader::new(&bytes[..]).with_warmup(Duration::ZERO);
+        assert_eq!(reader.poll_next().await.unwrap().attention, Some(50));
+        assert_eq!(reader.snapshot().attention, Some(50));
+        assert_eq!(reader.summary().total_packets, 1);
+    }
+
+    #[tokio::test]
+    async fn test_keep_raw() {
+        let payload = [0x04, 0x32, 0x42, 0x07];
+        let bytes = frame(&payload);
+
+        let mut reader = DataReader::new(&bytes[..]);
+        assert_eq!(reader.poll_next().await.unwrap().raw, None);
+        let mut reader = DataReader::new(&bytes[..]).with_keep_raw(true);
+        let packet = reader.poll_next().await.unwrap();
+        assert_eq!(packet.attention, Some(50));
+        assert_eq!(packet.raw, Some(payload.to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range() {
+        let bytes = [frame(&[0x04, 0xFF, 0x05, 0x28]), frame(&[0x04, 0x64])].concat();
+        let read = |action| {
+            let reader = DataReader::new(&bytes[..]).with_out_of_range(action);
+            StreamExt::collect::<Vec<_>>(reader)
+        };
+
+        let packets = read(OutOfRangeAction::Clamp).await;
+        let packet = packets[0].as_ref().unwrap();
+        assert_eq!((packet.attention, packet.meditation), (Some(100), Some(40)));
+        let packets = read(OutOfRangeAction::Drop).await;
+        let packet = packets[0].as_ref().unwrap();
+        assert_eq!((packet.attention, packet.meditation), (None, Some(40)));
+        let packets = read(OutOfRangeAction::Error).await;
+        assert!(matches!(
+            packets[0],
+            Err(CommError::OutOfRange {
+                field: "attention",
+                value: 0xFF
+            })
+        ));
+        // The reader keeps reading, and a value of 100 is valid
+        assert_eq!(packets[1].as_ref().unwrap().attention, Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_data_reader() {
+        let mut corrupted = frame(&[0x04, 0x32]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let bytes = [
+            vec![0x00, 0xAA],
+            frame(&[0x02, 0x00, 0x80, 0x02, 0xFF, 0xF9]),
+            corrupted,
+            vec![0xAA, 0xAA, 0xFA],
+            frame(&[0x05, 0x28]),
+            vec![0xAA, 0xAA, 0x04, 0x80],
+        ]
+        .concat();
+
+        let mut reader = DataReader::new(&bytes[..]).with_frame_errors(true);
+        let packet = reader.poll_next().await.unwrap();
+        assert_eq!(packet.poor_signal, Some(0));
+        assert_eq!(packet.raw_wave, Some(-7));
+        assert!(matches!(
+            reader.poll_next().await,
+            Err(CommError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            reader.poll_next().await,
+            Err(CommError::InvalidLength(0xFA))
+        ));
+        let next = reader.poll_next().await.unwrap();
+        assert_eq!(next.meditation, Some(40));
+        assert!(next.received_at.unwrap() >= packet.received_at.unwrap());
+        assert!(next.received_time.is_some());
+        assert!(matches!(
+            reader.poll_next().await,
+            Err(CommError::TruncatedPayload)
+        ));
+        assert_eq!(reader.state(), ReaderState::Ended);
+        assert_eq!(
+            reader.reset_stats(),
+            ReaderStats {
+                packets_ok: 2,
+                checksum_errors: 1,
+                invalid_lengths: 1,
+                truncated: 1,
+                ..ReaderStats::default()
+            }
+        );
+        assert_eq!(reader.stats(), ReaderStats::default());
+
+        // Corrupted frames are skipped by default
+        let reader = DataReader::new(std::io::Cursor::new(bytes));
+        let packets: Vec<_> = futures::StreamExt::collect(reader).await;
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[1].as_ref().unwrap().meditation, Some(40));
+        assert!(matches!(packets[2], Err(CommError::TruncatedPayload)));
+    }
+
+    #[tokio::test]
+    async fn test_read_packets() {
+        let bytes = frame(&[0x04, 0x32]).repeat(3);
+
+        let mut reader = DataReader::new(&bytes[..]);
+        let packets = reader.read_packets(2).await.unwrap();
+        assert_eq!(packets.len(), 2);
+        let partial = reader.read_packets(2).await.unwrap_err();
+        assert_eq!(partial.packets.len(), 1);
+        assert_eq!(partial.packets[0].attention, Some(50));
+        assert!(matches!(partial.error, CommError::Io(_)));
+
+        let mut reader = DataReader::new(&bytes[..]);
+        let partial = reader.read_for(Duration::from_secs(5)).await.unwrap_err();
+        assert_eq!(partial.packets.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_poll_next_variant() {
+        let bytes = [
+            frame(&[0x01, 0x64]),
+            frame(&[0x80, 0x02, 0xFF, 0xF9]),
+            frame(&[0x01, 0x64]),
+            frame(&[0x16, 0x50]),
+        ]
+        .concat();
+
+        let mut reader = DataReader::new(&bytes[..]);
+        assert_eq!(
+            reader.poll_next_variant().await.unwrap(),
+            PacketVariant::RawWave { raw_wave: -7 }
+        );
+        assert_eq!(
+            reader.poll_next_variant().await.unwrap(),
+            PacketVariant::Blink { blink_strength: 80 }
+        );
+        assert!(matches!(
+            reader.poll_next_variant().await,
+            Err(CommError::Io(_))
+        ));
+
+        let mut reader = DataReader::new(&bytes[..]);
+        let variants: Vec<_> = reader.variants().map(Result::unwrap).collect().await;
+        assert_eq!(variants.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_command() {
+        use tokio::io::AsyncReadExt;
+
+        let (stream, mut device) = tokio::io::duplex(64);
+        let mut reader = DataReader::new(stream);
+        reader.send_command(Command::RawOutput).await.unwrap();
+        reader.send_command(Command::Custom(0x10)).await.unwrap();
+        let mut bytes = [0; 2];
+        device.read_exact(&mut bytes).await.unwrap();
+        assert_eq!(bytes, [0x02, 0x10]);
+
+        // The device sees the end of the stream once the reader is closed
+        reader.close().await.unwrap();
+        assert_eq!(device.read(&mut bytes).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_split() {
+        let bytes = [
+            frame(&[0x80, 0x02, 0x00, 0x10]),
+            frame(&[0x04, 0x32]),
+            frame(&[0x02, 0x00, 0x80, 0x02, 0xFF, 0xF9]),
+        ]
+        .concat();
+
+        let reader = DataReader::new(std::io::Cursor::new(bytes));
+        let (mut raw, mut power) = reader.split(16, OverflowPolicy::Block);
+        assert_eq!(raw.poll_next().await.unwrap().raw_wave, Some(16));
+        assert_eq!(raw.poll_next().await.unwrap().raw_wave, Some(-7));
+        assert_eq!(power.poll_next().await.unwrap().attention, Some(50));
+        assert_eq!(power.poll_next().await.unwrap().poor_signal, Some(0));
+        // The end of the stream reaches both consumers
+        assert!(raw.poll_next().await.is_err());
+        assert!(power.poll_next().await.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let packet = Packet {
+            poor_signal: Some(0),
+            raw_wave: Some(-7),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&packet).unwrap();
+        assert_eq!(json, r#"{"poor_signal":0,"raw_wave":-7}"#);
+        assert_eq!(serde_json::from_str::<Packet>(&json).unwrap(), packet);
+
+        let variant = PacketVariant::Blink { blink_strength: 80 };
+        let json = serde_json::to_string(&variant).unwrap();
+        assert_eq!(json, r#"{"type":"Blink","blink_strength":80}"#);
+        assert!(matches!(
+            serde_json::from_str(&json).unwrap(),
+            PacketVariant::Blink { blink_strength: 80 }
+        ));
+    }
+
+    #[test]
+    fn test_comm_error() {
+        let e = CommError::from(Error::new(ErrorKind::BrokenPipe, "Connection lost"));
+        assert!(matches!(e, CommError::Io(_)));
+        assert_eq!(Error::from(e).kind(), ErrorKind::BrokenPipe);
+        let e = Error::from(CommError::TruncatedPayload);
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let e = Error::from(CommError::ChecksumMismatch {
+            expected: 0x0F,
+            got: 0xF0,
+        });
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(matches!(
+            e.get_ref().unwrap().downcast_ref::<CommError>(),
+            Some(CommError::ChecksumMismatch {
+                expected: 0x0F,
+                got: 0xF0
+            })
+        ));
+        assert_eq!(
+            CommError::InvalidLength(200).to_string(),
+            "Invalid payload length: 200"
+        );
+    }
+
+    #[test]
+    fn test_lock_thresholds() {
+        let thresholds = LockThresholds {
+            min_packet_rate: 256.0,
+            max_poor_signal: 25,
+        };
+        let snapshot = Snapshot {
+            poor_signal: Some(25),
+            packet_rate: 512.0,
+            synced: true,
+            ..Default::default()
+        };
+        assert!(thresholds.is_met(&snapshot));
+        for unlocked in [
+            Snapshot {
+                synced: false,
+                ..snapshot
+            },
+            Snapshot {
+                packet_rate: 1.0,
+                ..snapshot
+            },
+            Snapshot {
+                poor_signal: Some(26),
+                ..snapshot
+            },
+            Snapshot {
+                poor_signal: None,
+                ..snapshot
+            },
+        ] {
+            assert!(!thresholds.is_met(&unlocked));
+        }
+        assert!(LockThresholds::default().is_met(&Snapshot {
+            packet_rate: 1.0,
+            ..snapshot
+        }));
+    }
+
+    #[test]
+    fn test_interval_stats() {
+        let mut stats = IntervalStats::default();
+        stats.push(Duration::from_millis(2));
+        assert_eq!(stats.std_dev(), None);
+        stats.push(Duration::from_millis(2));
+        assert_eq!(stats.std_dev(), Some(Duration::ZERO));
+        stats.push(Duration::from_millis(1));
+        stats.push(Duration::from_millis(3));
+        let std_dev = stats.std_dev().unwrap().as_secs_f64();
+        assert!((std_dev - 0.000_707_1).abs() < 1e-6);
+        stats.reset();
+        assert_eq!(stats.std_dev(), None);
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut payload = vec![0x02, 0x1A, 0x42, 0x07, 0x83, 0x18];
+        payload.extend([0; 24]);
+        payload.extend([0x91, 0x03, 0x01, 0x02, 0x03, 0x55, 0x80, 0x02, 0x01]);
+        assert_eq!(
+            layout(&payload),
+            vec![
+                (Code::PoorSignal, 1),
+                (Code::Unknown, 1),
+                (Code::AsicEegPower, 24),
+                (Code::Unknown, 3),
+                (Code::Extended, 0),
+                (Code::RawWave, 1),
+            ]
+        );
+        let bytes = [frame(&[0x02, 0x00]), frame(&[0x80, 0x02, 0x00, 0x10])].concat();
+        assert_eq!(
+            layouts(&bytes),
+            vec![vec![(Code::PoorSignal, 1)], vec![(Code::RawWave, 2)]]
+        );
+    }
+
+    #[test]
+    fn test_extended_codes() {
+        // Codes at extended code levels 1 and 2, a single-byte one whose value
+        // looks like a code, and a multi-byte one whose value looks like an
+        // attention value, are skipped along with their values
+        let payload = [
+            0x55, 0x02, 0x04, 0x55, 0x55, 0x80, 0x02, 0x04, 0x63, 0x05, 0x28,
+        ];
+        let (packet, unknown_codes) = decode_payload(&payload, FramingMode::Lenient).unwrap();
+        assert_eq!(unknown_codes, 2);
+        assert_eq!(packet.poor_signal, None);
+        assert_eq!(packet.raw_wave, None);
+        assert_eq!(packet.attention, None);
+        assert_eq!(packet.meditation, Some(40));
+        assert_eq!(decode_payload(&payload, FramingMode::Strict), None);
+        // An extended code byte at the end has no code to apply to
+        let (packet, _) = decode_payload(&[0x04, 0x32, 0x55], FramingMode::Lenient).unwrap();
+        assert_eq!(packet.attention, Some(50));
+    }
+
+    #[test]
+    fn test_raw_marker() {
+        // The value of a marker is skipped like that of any single-byte code
+        let payload = [0x07, 0x05, 0x80, 0x02, 0x00, 0x10];
+        let (packet, unknown_codes) = decode_payload(&payload, FramingMode::Strict).unwrap();
+        assert_eq!(unknown_codes, 0);
+        assert_eq!(packet.raw_marker, Some(5));
+        assert_eq!(packet.raw_wave, Some(16));
+        assert_eq!(packet.to_string(), "raw=16 marker=5");
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let bytes = frame(&[0x02, 0x00, 0x04, 0x32]);
+        let packet = parse_frame(&bytes).unwrap();
+        assert_eq!(packet, parse_payload(&bytes[3..7]).unwrap());
+        assert_eq!(packet.attention, Some(50));
+
+        let mut corrupted = bytes.clone();
+        corrupted[7] ^= 0xFF;
+        assert!(matches!(
+            parse_frame(&corrupted),
+            Err(CommError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            parse_frame(&bytes[1..]),
+            Err(CommError::MissingSync)
+        ));
+        assert!(matches!(
+            parse_frame(&bytes[..7]),
+            Err(CommError::TruncatedPayload)
+        ));
+        assert!(matches!(
+            parse_frame(&[0xAA, 0xAA, 0xAA]),
+            Err(CommError::InvalidLength(0xAA))
+        ));
+        assert!(matches!(
+            parse_payload(&[0; 170]),
+            Err(CommError::InvalidLength(170))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        let mut power = vec![0x02, 0x00, 0x83, 0x18];
+        power.extend([0; 10]);
+        for payload in [
+            &[0x02, 0x00, 0x80, 0x02, 0x00][..],
+            &[0x02, 0x00, 0x80][..],
+            &[0x02, 0x00, 0x04][..],
+            &power[..],
+        ] {
+            let (packet, _) = decode_payload(payload, FramingMode::Lenient).unwrap();
+            assert_eq!(
+                packet,
+                Packet {
+                    poor_signal: Some(0),
+                    ..Default::default()
+                }
+            );
+            assert!(decode_payload(payload, FramingMode::Strict).is_none());
+        }
+    }
+
+    #[test]
+    fn test_framing_mode() {
+        let bytes = [
+            frame(&[0x02, 0x00, 0x42, 0x01]),
+            frame(&[0x80, 0x03, 0x00, 0x10, 0x00]),
+            frame(&[0x80, 0x02, 0x00, 0x10]),
+        ]
+        .concat();
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert!(frames.iter().all(|f| matches!(f, Frame::Packet(_))));
+
+        decoder.mode = FramingMode::Strict;
+        let frames: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert!(matches!(
+            frames[..],
+            [Frame::Rejected, Frame::Rejected, Frame::Packet(_)]
+        ));
+
+        decoder.max_payload = 3;
+        let frames: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert!(matches!(
+            frames[..],
+            [
+                Frame::InvalidLength(4),
+                Frame::InvalidLength(5),
+                Frame::InvalidLength(4)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_concatenated_payloads() {
+        let first = frame(&[0x04, 0x32]);
+        let second = frame(&[0x05, 0x28]);
+        // The second frame lost its sync bytes, and is followed by noise
+        // looking like a frame with a bad checksum
+        let noise = [0x02, 0x04, 0x10, 0x00];
+        let bytes = [&first[..], &second[2..], &noise, &first].concat();
+        let mut decoder = Decoder::new();
+        let frames: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        let meditation: Vec<_> = frames
+            .iter()
+            .map(|f| match f {
+                Frame::Packet(packet) => packet.meditation,
+                _ => panic!("Unexpected frame"),
+            })
+            .collect();
+        assert_eq!(meditation, vec![None, Some(0x28), None]);
+        assert!(decoder.is_idle());
+
+        decoder.mode = FramingMode::Strict;
+        let frames: Vec<_> = bytes.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(frames.len(), 2);
+    }
+
+    #[test]
+    fn test_callbacks() {
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut callbacks = Callbacks::default();
+        let attention = seen.clone();
+        callbacks.attention = Some(Box::new(move |x| attention.lock().unwrap().push(x as i32)));
+        let raw_wave = seen.clone();
+        callbacks.raw_wave = Some(Box::new(move |x| raw_wave.lock().unwrap().push(x as i32)));
+
+        callbacks.dispatch(&Packet {
+            attention: Some(60),
+            meditation: Some(40),
+            ..Default::default()
+        });
+        callbacks.dispatch(&Packet {
+            raw_wave: Some(-12),
+            ..Default::default()
+        });
+        assert_eq!(*seen.lock().unwrap(), vec![60, -12]);
+    }
+
+    #[test]
+    fn test_reader_state() {
+        let mut decoder = Decoder::new();
+        let bytes = frame(&[0x80, 0x02, 0x00, 0x10]);
+        let states: Vec<_> = bytes
+            .iter()
+            .map(|&b| {
+                decoder.push(b);
+                decoder.state()
+            })
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ReaderState::SearchingSync { found: 1 },
+                ReaderState::ReadingLength,
+                ReaderState::ReadingPayload { remaining: 4 },
+                ReaderState::ReadingPayload { remaining: 3 },
+                ReaderState::ReadingPayload { remaining: 2 },
+                ReaderState::ReadingPayload { remaining: 1 },
+                ReaderState::ReadingChecksum,
+                ReaderState::Idle,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new(Duration::from_secs(3600));
+        assert_eq!(throttle.allow(), Some(0));
+        assert_eq!(throttle.allow(), None);
+        assert_eq!(throttle.allow(), None);
+        throttle.last = Instant::now().checked_sub(Duration::from_secs(3600));
+        assert_eq!(throttle.allow(), Some(2));
+
+        let mut throttle = Throttle::new(Duration::ZERO);
+        assert_eq!(throttle.allow(), Some(0));
+        assert_eq!(throttle.allow(), Some(0));
+    }
+
+    #[test]
+    fn test_unknown_codes() {
+        let mut decoder = Decoder::new();
+        for byte in frame(&[0x02, 0x1A, 0x42, 0x43, 0x91, 0x01, 0x04]) {
+            decoder.push(byte);
+        }
+        assert_eq!(decoder.unknown_codes, 2);
+        for byte in frame(&[0x02, 0x1A]) {
+            decoder.push(byte);
         }
+        assert_eq!(decoder.unknown_codes, 0);
     }
 }
@@ -0,0 +1,120 @@
+//! Provides functionality to send configuration commands to the NeuroSky
+//! device over the same RFCOMM stream the data is read from. It defines the
+//! `Command` enum of supported command bytes and the `DeviceWriter` struct
+//! which writes them to the stream.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, command::Command, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream);
+//!
+//!     // Raw wave values in addition to eSense values and EEG power spectrum,
+//!     // at 57600 baud, the rate MyndBand devices run at, so the stream
+//!     // stays readable
+//!     reader.send_command(Command::RawOutput).await?;
+//!     println!("{:?}", reader.poll_next().await?);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Supported commands
+//!
+//! The ThinkGear module accepts single-byte configuration commands. Only the
+//! output mode commands below are known to be accepted by MyndBand devices;
+//! firmware that does not support a command silently ignores it.
+//!
+//! The ThinkGear protocol has no standby or sleep command. The output mode
+//! commands also set the baud rate of the serial link between the ThinkGear
+//! chip and the Bluetooth module, which the module does not necessarily
+//! follow, so a mode at another baud rate than the current one may turn the
+//! stream into garbage.
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Represents the configuration commands accepted by the ThinkGear module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// 9600 baud, eSense values and EEG power spectrum only (0x00)
+    NormalOutput,
+    /// 1200 baud, eSense values and EEG power spectrum only (0x01)
+    NormalOutput1200,
+    /// 57600 baud, raw wave values in addition to the normal output (0x02).
+    /// This is the default mode of MyndBand devices.
+    RawOutput,
+    /// Any other command byte
+    Custom(u8),
+}
+
+impl Command {
+    /// Returns the byte sent to the device for this command.
+    pub fn byte(self) -> u8 {
+        match self {
+            Command::NormalOutput => 0x00,
+            Command::NormalOutput1200 => 0x01,
+            Command::RawOutput => 0x02,
+            Command::Custom(byte) => byte,
+        }
+    }
+}
+
+/// Represents a writer that sends configuration commands to the NeuroSky
+/// device.
+pub struct DeviceWriter<W> {
+    /// The stream to write commands to
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> DeviceWriter<W> {
+    pub fn new(writer: W) -> DeviceWriter<W> {
+        DeviceWriter { writer }
+    }
+
+    /// Sends a single command to the device and flushes the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the stream fails.
+    pub async fn send(&mut self, command: Command) -> std::io::Result<()> {
+        self.writer.write_all(&[command.byte()]).await?;
+        self.writer.flush().await
+    }
+
+    /// Consumes the writer, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_device_writer() {
+        let (stream, mut device) = tokio::io::duplex(64);
+        let mut writer = DeviceWriter::new(stream);
+        for command in [
+            Command::NormalOutput,
+            Command::NormalOutput1200,
+            Command::RawOutput,
+            Command::Custom(0x10),
+        ] {
+            writer.send(command).await.unwrap();
+        }
+        drop(writer);
+        let mut bytes = Vec::new();
+        device.read_to_end(&mut bytes).await.unwrap();
+        assert_eq!(bytes, [0x00, 0x01, 0x02, 0x10]);
+    }
+}
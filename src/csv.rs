@@ -0,0 +1,196 @@
+//! Provides functionality to record packets into a CSV file for later
+//! analysis, e.g. with pandas or R. It defines the `CsvRecorder` struct which
+//! writes a header row, then one row per packet with the time it was
+//! received. Requires the `csv` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::{fs::File, io::BufWriter};
+//!
+//! use cerebrust::{comm::DataReader, csv::CsvRecorder, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream);
+//!     let mut csv = CsvRecorder::new(BufWriter::new(File::create("session.csv")?));
+//!     for _ in 0..512 * 60 {
+//!         csv.write(&reader.poll_next().await?)?;
+//!     }
+//!     csv.finish()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Format
+//!
+//! Raw wave and EEG power packets share a single wide table: every row has
+//! all columns, and the values a packet does not hold are left empty. The
+//! columns are `timestamp`, the single-byte values, `raw_wave`, `raw_wave8`,
+//! `raw_marker`, and one column per band of the EEG power spectrum, named after
+//! `Band::name`. The timestamp is the wall-clock time the packet was
+//! received, in seconds since the Unix epoch, and is empty for packets that
+//! were not read by a `DataReader`. Settings written with
+//! `CsvRecorder::with_meta` come first, as `# key: value` comment lines.
+
+use std::{
+    io::{self, Write},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    comm::{Band, Packet},
+    meta::SessionMeta,
+};
+
+/// Columns before the EEG power spectrum, in order.
+const COLUMNS: [&str; 9] = [
+    "timestamp",
+    "poor_signal",
+    "attention",
+    "meditation",
+    "blink_strength",
+    "battery",
+    "raw_wave",
+    "raw_wave8",
+    "raw_marker",
+];
+
+/// Represents a recorder writing packets as rows of CSV.
+pub struct CsvRecorder<W> {
+    /// The sink to write the CSV to
+    writer: W,
+    /// Settings of the session, written as comments before the header
+    meta: Option<SessionMeta>,
+    /// Whether the header row has been written
+    header_written: bool,
+}
+
+impl<W: Write> CsvRecorder<W> {
+    pub fn new(writer: W) -> CsvRecorder<W> {
+        CsvRecorder {
+            writer,
+            meta: None,
+            header_written: false,
+        }
+    }
+
+    /// Updates the settings of the session, written as `# key: value`
+    /// comment lines before the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The settings the session was captured with.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated recorder.
+    pub fn with_meta(mut self, meta: SessionMeta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
+
+    /// Writes a packet as a row, after the header if not written yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to record.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        let cell = |x: Option<String>| x.unwrap_or_default();
+        let timestamp = packet
+            .received_time
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| format!("{:.6}", time.as_secs_f64()));
+        let mut cells = vec![
+            cell(timestamp),
+            cell(packet.poor_signal.map(|x| x.to_string())),
+            cell(packet.attention.map(|x| x.to_string())),
+            cell(packet.meditation.map(|x| x.to_string())),
+            cell(packet.blink_strength.map(|x| x.to_string())),
+            cell(packet.battery.map(|x| x.to_string())),
+            cell(packet.raw_wave.map(|x| x.to_string())),
+            cell(packet.raw_wave8.map(|x| x.to_string())),
+            cell(packet.raw_marker.map(|x| x.to_string())),
+        ];
+        match packet.eeg_power {
+            Some(power) => cells.extend(power.as_array().map(|x| x.to_string())),
+            None => cells.resize(cells.len() + Band::ALL.len(), String::new()),
+        }
+        writeln!(self.writer, "{}", cells.join(","))
+    }
+
+    /// Writes the header, even if no packet was recorded, and flushes the
+    /// sink.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<W>` - The sink, e.g. to close the file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing or flushing fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some(meta) = &self.meta {
+            meta.write_comments(&mut self.writer)?;
+        }
+        let bands = Band::ALL.map(Band::name);
+        writeln!(self.writer, "{},{}", COLUMNS.join(","), bands.join(","))?;
+        self.header_written = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::comm::Power;
+
+    #[test]
+    fn test_csv_recorder() {
+        let mut csv = CsvRecorder::new(Vec::new());
+        csv.write(&Packet {
+            raw_wave: Some(-5),
+            received_time: Some(UNIX_EPOCH + Duration::from_millis(1500)),
+            ..Default::default()
+        })
+        .unwrap();
+        csv.write(&Packet {
+            poor_signal: Some(0),
+            eeg_power: Some(Power {
+                delta: 1,
+                mid_gamma: 8,
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let csv = String::from_utf8(csv.finish().unwrap()).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "timestamp,poor_signal,attention,meditation,blink_strength,battery,raw_wave,raw_wave8,raw_marker,delta,theta,low_alpha,high_alpha,low_beta,high_beta,low_gamma,mid_gamma",
+                "1.500000,,,,,,-5,,,,,,,,,,",
+                ",0,,,,,,,,1,0,0,0,0,0,0,8",
+            ]
+        );
+    }
+}
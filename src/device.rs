@@ -1,11 +1,12 @@
 //! Provides functionality to configure and connect to a NeuroSky device using
 //! bluetooth. It includes a `DeviceConfig` struct for specifying the bluetooth
-//! adapter, target device name, and RFCOMM channel, as well as methods for
-//! discovering and connecting to the target device.
+//! adapter, target device name, RFCOMM channel, discovery timing, and the
+//! output mode set after connecting, as well as methods for discovering
+//! nearby devices and connecting to the target device.
 //!
 //! # Examples
 //!
-//! ```rust
+//! ```rust,no_run
 //! use cerebrust::device::DeviceConfig;
 //!
 //! #[tokio::main]
@@ -26,16 +27,391 @@
 //!
 //! Methods in this module may return errors related to Bluetooth session creation,
 //! adapter retrieval, device discovery, and stream connection. These errors are
-//! propagated as `bluer::Result` types.
+//! propagated as `Error` types, which convert into `bluer::Error` and
+//! `std::io::Error`. A target device found in Bluetooth Low Energy mode is
+//! reported as `Error::DeviceInBleMode`, as it cannot be reached over RFCOMM.
 
-use std::{io, time::Duration};
+use std::{
+    collections::{HashSet, VecDeque},
+    env, fmt, fs, io,
+    path::Path,
+    pin::Pin,
+    time::{Duration, Instant},
+};
 
 use bluer::{
-    Adapter, AdapterEvent, Address, Session,
-    rfcomm::{SocketAddr, Stream},
+    Adapter, AdapterEvent, Address, AddressType, Device, Session, Uuid,
+    rfcomm::{Security, SecurityLevel, Socket, SocketAddr, Stream},
 };
 use futures::{StreamExt, pin_mut};
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout};
+
+use crate::command::{Command, DeviceWriter};
+
+/// Represents the errors that can occur while connecting to the device.
+#[derive(Debug)]
+pub enum Error {
+    /// The target device was found, but only advertises itself over
+    /// Bluetooth Low Energy, which does not support RFCOMM
+    DeviceInBleMode { address: Address },
+    /// No Bluetooth session could be created, usually because the Bluetooth
+    /// daemon is not running or not reachable over D-Bus, or because the
+    /// user lacks the permissions to use it
+    SessionUnavailable(bluer::Error),
+    /// The target device did not accept the RFCOMM connection in time,
+    /// usually because it is already connected to another host, e.g. a phone
+    ConnectTimeout { address: Address, timeout: Duration },
+    /// The target device refused the RFCOMM connection at the security
+    /// level requested, usually because it requires an authenticated and
+    /// encrypted link, or because it is not paired
+    AuthenticationRequired { address: Address },
+    /// The whole connection sequence, discovery included, did not complete
+    /// within the time budget given to `DeviceConfig::connect_with_timeout`
+    TimedOut { timeout: Duration },
+    /// The adapter is powered off, and powering it on is disabled with
+    /// `DeviceConfig::with_auto_power`
+    AdapterPoweredOff { adapter: String },
+    /// Any other Bluetooth error
+    Bluetooth(bluer::Error),
+}
+
+/// Result type of the methods in this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeviceInBleMode { address } => write!(
+                f,
+                "Device {} is in BLE mode, switch it to classic Bluetooth mode (see the device manual) and try again",
+                address
+            ),
+            Error::SessionUnavailable(e) => write!(
+                f,
+                "Bluetooth session unavailable ({}), make sure bluetoothd is running and reachable over D-Bus, and that the user may use it (e.g. is in the `bluetooth` group)",
+                e
+            ),
+            Error::ConnectTimeout { address, timeout } => write!(
+                f,
+                "Device {} did not accept the RFCOMM connection within {:?}, make sure it is not connected to another host and try again",
+                address, timeout
+            ),
+            Error::AuthenticationRequired { address } => write!(
+                f,
+                "Device {} refused the connection for security reasons, pair with it and request encryption with `DeviceConfig::with_security(SecurityLevel::Medium)` or higher",
+                address
+            ),
+            Error::TimedOut { timeout } => {
+                write!(
+                    f,
+                    "Connecting to the device did not complete within {:?}",
+                    timeout
+                )
+            }
+            Error::AdapterPoweredOff { adapter } => write!(
+                f,
+                "Adapter {} is powered off, power it on (e.g. `bluetoothctl power on`) or enable `DeviceConfig::with_auto_power`",
+                adapter
+            ),
+            Error::Bluetooth(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SessionUnavailable(e) | Error::Bluetooth(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<bluer::Error> for Error {
+    fn from(e: bluer::Error) -> Self {
+        Error::Bluetooth(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Bluetooth(e.into())
+    }
+}
+
+impl From<Error> for bluer::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Bluetooth(e) => return e,
+            Error::DeviceInBleMode { .. } => bluer::ErrorKind::NotSupported,
+            Error::SessionUnavailable(ref e) => e.kind.clone(),
+            Error::AuthenticationRequired { .. } => bluer::ErrorKind::AuthenticationRejected,
+            Error::AdapterPoweredOff { .. } => bluer::ErrorKind::NotReady,
+            Error::ConnectTimeout { .. } | Error::TimedOut { .. } => {
+                bluer::ErrorKind::Internal(bluer::InternalErrorKind::Io(io::ErrorKind::TimedOut))
+            }
+        };
+        bluer::Error {
+            kind,
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        bluer::Error::from(e).into()
+    }
+}
+
+/// UUID of the Serial Port Profile, the classic Bluetooth service carrying
+/// the ThinkGear stream over RFCOMM.
+pub const SERIAL_PORT_UUID: Uuid = Uuid::from_u128(0x0000_1101_0000_1000_8000_0080_5f9b_34fb);
+
+/// Whether the services advertised by a device, as returned by
+/// `DeviceConfig::services`, include the Serial Port Profile, i.e. whether
+/// the device can be reached over RFCOMM.
+///
+/// # Arguments
+///
+/// * `services` - The service UUIDs advertised by the device.
+///
+/// # Returns
+///
+/// * `bool` - Whether `SERIAL_PORT_UUID` is among them.
+pub fn has_serial_port(services: &HashSet<Uuid>) -> bool {
+    services.contains(&SERIAL_PORT_UUID)
+}
+
+/// Whether the device only advertises itself over Bluetooth Low Energy, i.e.
+/// it has an LE address and no classic Bluetooth class of device.
+async fn is_ble_only(device: &Device) -> Result<bool> {
+    let le = matches!(
+        device.address_type().await?,
+        AddressType::LePublic | AddressType::LeRandom
+    );
+    Ok(le && device.class().await?.is_none())
+}
+
+/// Returns the address of the target device, unless it is in BLE mode.
+async fn found(device: &Device) -> Result<Address> {
+    let address = device.address();
+    if is_ble_only(device).await? {
+        return Err(Error::DeviceInBleMode { address });
+    }
+    Ok(address)
+}
+
+/// Represents a device found during discovery, see
+/// `DeviceConfig::discover_devices`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Address of the device
+    pub address: Address,
+    /// Name of the device, `None` if it could not be resolved
+    pub name: Option<String>,
+    /// Signal strength of the device when it was found (dBm), `None` if
+    /// unknown. The higher, the nearer; sort by it to pick the nearest of
+    /// several devices.
+    pub rssi: Option<i16>,
+    /// Whether the device is paired with the adapter
+    pub paired: bool,
+}
+
+impl DeviceInfo {
+    /// Reads the properties of a device known to the adapter. Properties that
+    /// cannot be read are left unknown, so one device does not abort the
+    /// discovery.
+    async fn read(device: &Device, name: Option<String>) -> DeviceInfo {
+        DeviceInfo {
+            address: device.address(),
+            name,
+            rssi: device.rssi().await.ok().flatten(),
+            paired: device.is_paired().await.unwrap_or(false),
+        }
+    }
+}
+
+/// Represents the device a stream is connected to, kept to monitor the
+/// signal strength of the connection while streaming, e.g. to warn the user
+/// before the link drops. Returned by `DeviceConfig::connect_monitored`.
+///
+/// The signal strength is the one reported by BlueZ, which refreshes it
+/// whenever the adapter receives it, e.g. during discovery. Some stacks do
+/// not refresh it while connected, in which case readings stay constant or
+/// are unavailable.
+pub struct RssiMonitor {
+    /// The connected device
+    device: Device,
+}
+
+impl RssiMonitor {
+    /// Returns the connected device, e.g. to read its other properties.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Reads the current signal strength of the connection.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<i16>>` - The signal strength (dBm), `None` if not
+    ///   known.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the device fails, e.g.
+    /// once it has been removed from the adapter.
+    pub async fn current_rssi(&self) -> Result<Option<i16>> {
+        Ok(self.device.rssi().await?)
+    }
+
+    /// Reads the signal strength at a fixed interval, e.g. to feed
+    /// `DataReader::set_rssi`. Ticks at which it is not known, or cannot be
+    /// read, are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The time between two readings. Must be non-zero.
+    ///
+    /// # Returns
+    ///
+    /// * `impl Stream<Item = i16>` - The signal strength (dBm) at each tick,
+    ///   the first one right away. The stream never ends.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `interval` is zero.
+    pub fn watch(&self, interval: Duration) -> impl futures::Stream<Item = i16> + '_ {
+        let ticks = tokio::time::interval(interval);
+        futures::stream::unfold(ticks, move |mut ticks| async move {
+            loop {
+                ticks.tick().await;
+                if let Ok(Some(rssi)) = self.device.rssi().await {
+                    return Some((rssi, ticks));
+                }
+            }
+        })
+    }
+}
+
+/// State of the stream returned by `DeviceConfig::discover_devices`.
+struct Discovery {
+    /// Events of the adapter scan, `None` until the scan is started and once
+    /// it has ended
+    events: Option<Pin<Box<dyn futures::Stream<Item = AdapterEvent> + Send>>>,
+    /// Time at which the discovery ends
+    deadline: Instant,
+    /// Devices seen so far, so each one is yielded once
+    seen: HashSet<Address>,
+    /// Devices seen without a name yet
+    unnamed: Vec<Address>,
+    /// Devices ready to be yielded
+    ready: VecDeque<DeviceInfo>,
+    /// Whether the discovery has ended
+    ended: bool,
+}
+
+impl Discovery {
+    /// Waits for the next discovered device, `None` once the discovery has
+    /// ended and all devices were yielded.
+    async fn next(
+        &mut self,
+        config: &DeviceConfig,
+        adapter: &Adapter,
+    ) -> Option<Result<DeviceInfo>> {
+        loop {
+            if let Some(item) = self.ready.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.ended {
+                return None;
+            }
+            let events = match &mut self.events {
+                Some(events) => events,
+                None => match adapter.discover_devices().await {
+                    Ok(events) => self.events.insert(Box::pin(events)),
+                    Err(e) => return self.fail(e.into()),
+                },
+            };
+            let remaining = self.deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                // Stop the scan, and report devices whose name never resolved
+                self.events = None;
+                self.ended = true;
+                for addr in std::mem::take(&mut self.unnamed) {
+                    match adapter.device(addr) {
+                        Ok(device) => self.ready.push_back(DeviceInfo::read(&device, None).await),
+                        Err(e) => return self.fail(e.into()),
+                    }
+                }
+                continue;
+            }
+            match timeout(remaining.min(config.poll_interval), events.next()).await {
+                Ok(Some(AdapterEvent::DeviceAdded(addr))) => {
+                    if !self.seen.insert(addr) {
+                        continue;
+                    }
+                    let device = match adapter.device(addr) {
+                        Ok(device) => device,
+                        Err(e) => return self.fail(e.into()),
+                    };
+                    // The name property may not be available yet, in which
+                    // case the device is re-checked later
+                    match device.name().await {
+                        Ok(Some(name)) => {
+                            return Some(Ok(DeviceInfo::read(&device, Some(name)).await));
+                        }
+                        Ok(None) | Err(_) => self.unnamed.push(addr),
+                    }
+                }
+                Ok(Some(_)) => continue,
+                Ok(None) => {
+                    return self.fail(
+                        io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "Device discovery stopped unexpectedly",
+                        )
+                        .into(),
+                    );
+                }
+                Err(_) => {
+                    // Re-check devices whose names may have been resolved since
+                    let mut i = 0;
+                    while i < self.unnamed.len() {
+                        let addr = self.unnamed[i];
+                        let device = match adapter.device(addr) {
+                            Ok(device) => device,
+                            Err(e) => return self.fail(e.into()),
+                        };
+                        match device.name().await {
+                            Ok(Some(name)) => {
+                                self.unnamed.swap_remove(i);
+                                self.ready
+                                    .push_back(DeviceInfo::read(&device, Some(name)).await);
+                            }
+                            // Report devices whose name cannot be read
+                            // without one, so one of them does not abort the
+                            // discovery
+                            Err(_) => {
+                                self.unnamed.swap_remove(i);
+                                self.ready.push_back(DeviceInfo::read(&device, None).await);
+                            }
+                            Ok(None) => i += 1,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the discovery with an error.
+    fn fail(&mut self, e: Error) -> Option<Result<DeviceInfo>> {
+        self.events = None;
+        self.ended = true;
+        Some(Err(e))
+    }
+}
 
 /// Configuration for connecting to a NeuroSky device over Bluetooth.
 #[derive(Debug)]
@@ -50,6 +426,22 @@ pub struct DeviceConfig {
     pub address: Option<Address>,
     /// RFCOMM channel. Default: 5.
     pub channel: u8,
+    /// Total time budget for discovering the target device. Default: 10s.
+    pub discovery_timeout: Duration,
+    /// Interval at which discovery wakes up to check the time budget and
+    /// re-check devices whose names were not resolved yet. Default: 1s.
+    pub poll_interval: Duration,
+    /// Time budget for the RFCOMM connection to be accepted. Default: 10s.
+    pub connect_timeout: Duration,
+    /// Security level requested for the RFCOMM connection. If not provided,
+    /// the kernel default is used, i.e. no authentication or encryption.
+    pub security: Option<SecurityLevel>,
+    /// Command sent right after connecting, to put the device into the
+    /// desired output mode, if any. Default: none, the device is left in the
+    /// mode it is in.
+    pub initial_command: Option<Command>,
+    /// Whether the adapter is powered on if it is off. Default: true.
+    pub auto_power: bool,
 }
 
 impl Default for DeviceConfig {
@@ -59,6 +451,12 @@ impl Default for DeviceConfig {
             target_name: None,
             address: None,
             channel: 5,
+            discovery_timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_secs(1),
+            connect_timeout: Duration::from_secs(10),
+            security: None,
+            initial_command: None,
+            auto_power: true,
         }
     }
 }
@@ -125,28 +523,244 @@ impl DeviceConfig {
         self
     }
 
-    /// Gets the default Bluetooth adapter and powers it on.
+    /// Updates the total time budget for discovering the target device.
+    /// If not provided, the default timeout is 10 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The total time budget for discovery.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn with_discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.discovery_timeout = timeout;
+        self
+    }
+
+    /// Updates the interval at which discovery checks its time budget and
+    /// re-checks devices whose names were not resolved yet.
+    /// If not provided, the default interval is 1 second.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The polling interval during discovery.
     ///
     /// # Returns
     ///
-    /// * `bluer::Result<Adapter>` - The default Bluetooth adapter.
+    /// * `Self` - The updated configuration.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Updates the time budget for the RFCOMM connection to be accepted by
+    /// the target device, once found. A device that is already connected to
+    /// another host may otherwise keep the connection pending forever.
+    /// If not provided, the default timeout is 10 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The time budget for the RFCOMM connection.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Updates the security level requested for the RFCOMM connection. Some
+    /// bonded devices refuse unencrypted connections, which
+    /// `SecurityLevel::Medium` (authentication and encryption) or higher
+    /// fixes. The device must be paired beforehand.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The security level to request.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn with_security(mut self, level: SecurityLevel) -> Self {
+        self.security = Some(level);
+        self
+    }
+
+    /// Updates the command sent right after connecting, to put the device
+    /// into the desired output mode. If not provided, no command is sent and
+    /// the device is left in the mode it is in. Output mode commands also set
+    /// the baud rate of the ThinkGear chip, so only send one the Bluetooth
+    /// module of the device follows, e.g. `Command::RawOutput` (57600 baud)
+    /// on a MyndBand, or the stream may turn into garbage; see `probe` to
+    /// tell the current mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send after connecting.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn with_initial_command(mut self, command: Command) -> Self {
+        self.initial_command = Some(command);
+        self
+    }
+
+    /// Disables the command sent right after connecting, for applications
+    /// managing the output mode themselves. This is the default.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn without_initial_command(mut self) -> Self {
+        self.initial_command = None;
+        self
+    }
+
+    /// Updates whether the adapter is powered on if it is off. Disable it to
+    /// manage the adapter state yourself, e.g. on shared machines, or where
+    /// powering the adapter requires privileges the user lacks. Default:
+    /// true.
+    ///
+    /// # Arguments
+    ///
+    /// * `auto_power` - Whether to power the adapter on.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated configuration.
+    pub fn with_auto_power(mut self, auto_power: bool) -> Self {
+        self.auto_power = auto_power;
+        self
+    }
+
+    /// Gets the default Bluetooth adapter and powers it on, unless disabled
+    /// with `with_auto_power`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Adapter>` - The default Bluetooth adapter.
     ///
     /// # Errors
     ///
     /// This function will return an error if the session creation, adapter retrieval,
-    /// or powering on the adapter fails.
-    pub async fn get_adapter(&self) -> bluer::Result<Adapter> {
-        let session = Session::new().await?;
+    /// or powering on the adapter fails. A failed session creation is reported
+    /// as `Error::SessionUnavailable`. If the adapter is off and powering it on
+    /// is disabled, `Error::AdapterPoweredOff` is returned.
+    pub async fn get_adapter(&self) -> Result<Adapter> {
+        let session = Session::new().await.map_err(Error::SessionUnavailable)?;
         let adapter = if let Some(name) = &self.adapter {
             session.adapter(name)?
         } else {
             session.default_adapter().await?
         };
-        adapter.set_powered(true).await?;
+        if self.auto_power {
+            adapter.set_powered(true).await?;
+        } else if !adapter.is_powered().await? {
+            return Err(Error::AdapterPoweredOff {
+                adapter: adapter.name().to_string(),
+            });
+        }
         Ok(adapter)
     }
 
-    /// Discovers the target Bluetooth device by name using the provided adapter.
+    /// Returns the UUIDs of the services advertised by a device known to the
+    /// adapter, e.g. after discovery, to detect which transports it supports
+    /// (see `has_serial_port`). For classic Bluetooth devices, BlueZ fills
+    /// them from the SDP records once the device has been connected or
+    /// paired. The SDP records themselves, including the RFCOMM channel, are
+    /// not exposed by BlueZ.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the device.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashSet<Uuid>>` - The advertised service UUIDs, empty if
+    ///   none are known yet.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the adapter cannot be retrieved,
+    /// or if the device is not known to the adapter.
+    pub async fn services(&self, addr: Address) -> Result<HashSet<Uuid>> {
+        let device = self.get_adapter().await?.device(addr)?;
+        Ok(device.uuids().await?.unwrap_or_default())
+    }
+
+    /// Discovers all nearby Bluetooth devices using the provided adapter,
+    /// without filtering, e.g. to let the user pick a device. Discovery runs
+    /// for at most `discovery_timeout` in total, after which the stream ends.
+    /// Each device is yielded once, as soon as its name is known. Devices
+    /// whose names are not known yet when they are added are re-checked every
+    /// `poll_interval`, as names are often resolved after the device shows up;
+    /// those whose name cannot be resolved are yielded without one, at the
+    /// latest when discovery ends.
+    ///
+    /// ```rust,no_run
+    /// # use cerebrust::device::DeviceConfig;
+    /// # use futures::{StreamExt, pin_mut};
+    /// # async fn example() -> cerebrust::device::Result<()> {
+    /// let config = DeviceConfig::default();
+    /// let adapter = config.get_adapter().await?;
+    /// let devices = config.discover_devices(&adapter);
+    /// pin_mut!(devices);
+    /// let mut found = Vec::new();
+    /// while let Some(device) = devices.next().await {
+    ///     found.push(device?);
+    /// }
+    /// // Nearest devices first
+    /// found.sort_by_key(|device| std::cmp::Reverse(device.rssi));
+    /// for device in found {
+    ///     let name = device.name.as_deref().unwrap_or("(unknown)");
+    ///     println!("{} {} {:?}dBm", device.address, name, device.rssi);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - A reference to the Bluetooth adapter to use for discovery.
+    ///
+    /// # Returns
+    ///
+    /// * `impl Stream<Item = Result<DeviceInfo>>` - The address, name, signal
+    ///   strength, and pairing state of each discovered device, in discovery
+    ///   order.
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error, and ends, if starting the discovery or
+    /// querying the adapter fails, or if the discovery stops unexpectedly.
+    ///
+    /// # Cancellation
+    ///
+    /// The adapter scan lives as long as the returned stream, and stops once
+    /// the discovery ends or the stream is dropped.
+    pub fn discover_devices<'a>(
+        &'a self,
+        adapter: &'a Adapter,
+    ) -> impl futures::Stream<Item = Result<DeviceInfo>> + 'a {
+        let discovery = Discovery {
+            events: None,
+            deadline: Instant::now() + self.discovery_timeout,
+            seen: HashSet::new(),
+            unnamed: Vec::new(),
+            ready: VecDeque::new(),
+            ended: false,
+        };
+        futures::stream::unfold(discovery, move |mut discovery| async move {
+            let item = discovery.next(self, adapter).await?;
+            Some((item, discovery))
+        })
+    }
+
+    /// Discovers the target Bluetooth device by name using the provided
+    /// adapter, see `discover_devices`.
     ///
     /// # Arguments
     ///
@@ -154,13 +768,20 @@ impl DeviceConfig {
     ///
     /// # Returns
     ///
-    /// * `bluer::Result<Address>` - The address of the discovered target device.
+    /// * `Result<Address>` - The address of the discovered target device.
     ///
     /// # Errors
     ///
     /// This function will return an error if no target device name is provided,
-    /// or if device discovery fails or times out.
-    pub async fn try_find_device(&self, adapter: &Adapter) -> bluer::Result<Address> {
+    /// or if device discovery fails or times out. If the target device is
+    /// found in BLE mode, `Error::DeviceInBleMode` is returned.
+    ///
+    /// # Cancellation
+    ///
+    /// The adapter scan lives as long as the discovery stream owned by the
+    /// returned future. Dropping the future, e.g. when a timeout elsewhere
+    /// fires, drops the stream and stops the scan.
+    pub async fn try_find_device(&self, adapter: &Adapter) -> Result<Address> {
         let target_name = match &self.target_name {
             Some(name) => name,
             None => {
@@ -171,33 +792,102 @@ impl DeviceConfig {
                 .into());
             }
         };
-        let device_events = adapter.discover_devices().await?;
-        pin_mut!(device_events);
+        let devices = self.discover_devices(adapter);
+        pin_mut!(devices);
+        while let Some(item) = devices.next().await {
+            let info = item?;
+            if info.name.as_ref() == Some(target_name) {
+                return found(&adapter.device(info.address)?).await;
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Device discovery timed out").into())
+    }
 
-        loop {
-            match timeout(Duration::from_secs(1), device_events.next()).await {
-                Ok(Some(AdapterEvent::DeviceAdded(addr))) => {
-                    let device = adapter.device(addr)?;
-                    match device.name().await? {
-                        Some(name) if name == *target_name => {
-                            return Ok(addr);
-                        }
-                        _ => continue,
-                    }
-                }
-                Ok(_) => continue,
-                Err(_) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::TimedOut,
-                        "Device discovery timed out",
-                    )
-                    .into());
-                }
+    /// Looks up the target device by name among the devices already paired
+    /// with the adapter, without scanning, so reconnecting to a paired
+    /// device is near-instant and no scan interferes with the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - A reference to the Bluetooth adapter the device is paired with.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Address>>` - The address of the paired target device,
+    ///   `None` if no target device name is provided or no paired device has
+    ///   that name.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the adapter fails. If
+    /// the target device is paired in BLE mode, `Error::DeviceInBleMode` is
+    /// returned.
+    pub async fn find_paired_device(&self, adapter: &Adapter) -> Result<Option<Address>> {
+        let Some(target_name) = &self.target_name else {
+            return Ok(None);
+        };
+        for addr in adapter.device_addresses().await? {
+            let device = adapter.device(addr)?;
+            // Devices whose properties cannot be read are skipped, so one
+            // stale entry does not abort the lookup
+            if device.is_paired().await.unwrap_or(false)
+                && device.name().await.ok().flatten().as_ref() == Some(target_name)
+            {
+                return found(&device).await.map(Some);
             }
         }
+        Ok(None)
+    }
+
+    /// Waits for the adapter to stop scanning after discovery has finished.
+    /// The scan is stopped in the background once the discovery stream is
+    /// dropped, so connecting right away may race with a scan that is still
+    /// running. Gives up after `poll_interval`, as another application may
+    /// keep the adapter scanning.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - A reference to the Bluetooth adapter used for discovery.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if querying the adapter fails.
+    async fn wait_discovery_stopped(&self, adapter: &Adapter) -> Result<()> {
+        let deadline = Instant::now() + self.poll_interval;
+        while adapter.is_discovering().await? && Instant::now() < deadline {
+            sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    }
+
+    /// Discovers the target device like `try_find_device`, then waits for the
+    /// adapter to stop scanning, so the connection that follows does not
+    /// race with the scan. This is the path `connect` takes when the target
+    /// device is not paired.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter` - A reference to the Bluetooth adapter to use for discovery.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Address>` - The address of the discovered target device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if discovery fails, see
+    /// `try_find_device`, or if querying the adapter fails.
+    pub(crate) async fn discover_target(&self, adapter: &Adapter) -> Result<Address> {
+        let addr = self.try_find_device(adapter).await?;
+        self.wait_discovery_stopped(adapter).await?;
+        Ok(addr)
     }
 
-    /// Builds an RFCOMM connection to the target device address.
+    /// Builds an RFCOMM connection to the target device address. If an
+    /// adapter name is configured, the socket is bound to that adapter, so
+    /// the connection does not go out through another adapter on machines
+    /// with several of them. Otherwise, the Bluetooth stack picks one. Once
+    /// connected, the initial command is sent, if any.
     ///
     /// # Arguments
     ///
@@ -205,37 +895,439 @@ impl DeviceConfig {
     ///
     /// # Returns
     ///
-    /// * `bluer::Result<Stream>` - The RFCOMM stream connected to the target device.
+    /// * `Result<Stream>` - The RFCOMM stream connected to the target device.
     ///
     /// # Errors
     ///
-    /// This function will return an error if the stream connection fails.
-    pub async fn build_connection(&self, addr: Address) -> bluer::Result<Stream> {
-        let target_sa = SocketAddr::new(addr, self.channel);
-        let stream = Stream::connect(target_sa).await?;
+    /// This function will return an error if the configured adapter cannot be
+    /// retrieved, or if the stream connection fails. If the connection is not
+    /// accepted within the connect timeout, `Error::ConnectTimeout` is returned.
+    /// If it is refused for security reasons, `Error::AuthenticationRequired`
+    /// is returned.
+    pub async fn build_connection(&self, addr: Address) -> Result<Stream> {
+        // The adapter address is only needed to bind the socket
+        let adapter = match self.adapter {
+            Some(_) => Some(self.get_adapter().await?.address().await?),
+            None => None,
+        };
+        self.connect_from(adapter, addr).await
+    }
+
+    /// Returns the local address the RFCOMM socket is bound to: the address
+    /// of the adapter in use if an adapter name is configured, so the
+    /// connection goes out through that adapter, or `None` to let the
+    /// Bluetooth stack pick one.
+    fn bind_address(&self, adapter: Option<Address>) -> Option<SocketAddr> {
+        self.adapter
+            .as_ref()
+            .and(adapter)
+            .map(|addr| SocketAddr::new(addr, 0))
+    }
+
+    /// Builds an RFCOMM connection to the target device address, with the
+    /// socket bound as chosen by `bind_address` given the address of the
+    /// adapter in use, if known, and sends the initial command.
+    async fn connect_from(&self, adapter: Option<Address>, addr: Address) -> Result<Stream> {
+        let socket = Socket::new()?;
+        if let Some(local) = self.bind_address(adapter) {
+            socket.bind(local)?;
+        }
+        if let Some(level) = self.security {
+            // The key size only applies to Bluetooth Low Energy
+            socket.set_security(Security { level, key_size: 0 })?;
+        }
+        let connect = socket.connect(SocketAddr::new(addr, self.channel));
+        let mut stream = timeout(self.connect_timeout, connect)
+            .await
+            .map_err(|_| Error::ConnectTimeout {
+                address: addr,
+                timeout: self.connect_timeout,
+            })?
+            .map_err(|e| match e.kind() {
+                // The kernel reports a connection refused by the security
+                // policy of either side as EACCES
+                io::ErrorKind::PermissionDenied => Error::AuthenticationRequired { address: addr },
+                _ => e.into(),
+            })?;
+        if let Some(command) = self.initial_command {
+            DeviceWriter::new(&mut stream).send(command).await?;
+        }
         Ok(stream)
     }
 
+    /// Saves the address, name and RFCOMM channel of the target device to a
+    /// small file, so the next session can reconnect with `load_last`
+    /// without discovery. After discovery, the resolved address is the peer
+    /// address of the stream:
+    ///
+    /// ```rust,no_run
+    /// # use cerebrust::device::DeviceConfig;
+    /// # async fn example() -> std::io::Result<()> {
+    /// let config = DeviceConfig::default();
+    /// let stream = config.connect().await?;
+    /// config
+    ///     .with_address(stream.peer_addr()?.addr)
+    ///     .save_last("last_device")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write, replaced if it exists.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<()>` - Ok if the file was written.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `InvalidInput` error if the address is
+    /// not set, or the error of writing the file.
+    pub fn save_last(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let address = self.address.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Device address is not set")
+        })?;
+        let mut contents = format!("address={}\nchannel={}\n", address, self.channel);
+        if let Some(name) = &self.target_name {
+            contents.push_str(&format!("name={}\n", name));
+        }
+        fs::write(path, contents)
+    }
+
+    /// Loads the target device saved by `save_last`. The returned
+    /// configuration connects directly to the saved address, with the
+    /// other settings at their default.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file written by `save_last`.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<DeviceConfig>` - The configuration of the saved device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the error of reading the file, or an
+    /// `InvalidData` error if the file is malformed or has no address.
+    pub fn load_last(path: impl AsRef<Path>) -> io::Result<DeviceConfig> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut config = DeviceConfig::default();
+        for line in fs::read_to_string(path)?.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key {
+                "address" => {
+                    config.address = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(format!("Invalid address: {}", value)))?,
+                    )
+                }
+                "channel" => {
+                    config.channel = value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid channel: {}", value)))?
+                }
+                "name" => config.target_name = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if config.address.is_none() {
+            return Err(invalid("No device address saved".to_string()));
+        }
+        Ok(config)
+    }
+
+    /// Builds a configuration from environment variables, e.g. to point the
+    /// same binary at different headsets across machines. Settings whose
+    /// variable is unset or empty keep their default:
+    ///
+    /// - `CEREBRUST_ADAPTER`: The name of the Bluetooth adapter, e.g. `hci1`.
+    /// - `CEREBRUST_DEVICE_NAME`: The name of the target device.
+    /// - `CEREBRUST_CHANNEL`: The RFCOMM channel (0 ~ 255).
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<DeviceConfig>` - The configuration from the environment.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an `InvalidData` error if a variable is not
+    /// valid Unicode, or if the channel is not a valid number.
+    pub fn from_env() -> io::Result<DeviceConfig> {
+        DeviceConfig::from_vars(|key| match env::var(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid {}: {}", key, e),
+            )),
+        })
+    }
+
+    /// Builds a configuration from variables looked up by name, see
+    /// `from_env`.
+    fn from_vars(var: impl Fn(&str) -> io::Result<Option<String>>) -> io::Result<DeviceConfig> {
+        let var = |key| var(key).map(|value| value.filter(|value| !value.is_empty()));
+        let mut config = DeviceConfig::default();
+        if let Some(adapter) = var("CEREBRUST_ADAPTER")? {
+            config.adapter = Some(adapter);
+        }
+        if let Some(name) = var("CEREBRUST_DEVICE_NAME")? {
+            config.target_name = Some(name);
+        }
+        if let Some(channel) = var("CEREBRUST_CHANNEL")? {
+            config.channel = channel.trim().parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Invalid CEREBRUST_CHANNEL: {} (Expected an RFCOMM channel, 0 ~ 255)",
+                        channel
+                    ),
+                )
+            })?;
+        }
+        Ok(config)
+    }
+
     /// One-liner to get the default Bluetooth adapter, discover the target device,
-    /// and build an RFCOMM connection to it.
+    /// and build an RFCOMM connection to it. A target device already paired
+    /// with the adapter is connected to without discovery, see
+    /// `find_paired_device`.
     ///
     /// # Returns
     ///
-    /// * `bluer::Result<Stream>` - The RFCOMM stream connected to the target device.
+    /// * `Result<Stream>` - The RFCOMM stream connected to the target device.
     ///
     /// # Errors
     ///
     /// This function will return an error if the default adapter retrieval, device
-    /// discovery, or stream connection fails.
-    pub async fn connect(&self) -> bluer::Result<Stream> {
+    /// discovery, or stream connection fails. If the target device is found in
+    /// BLE mode, `Error::DeviceInBleMode` is returned instead of attempting to
+    /// connect. If the connection is not accepted within the connect timeout,
+    /// `Error::ConnectTimeout` is returned. If it is refused for security
+    /// reasons, `Error::AuthenticationRequired` is returned.
+    pub async fn connect(&self) -> Result<Stream> {
         match self.address {
             Some(addr) => self.build_connection(addr).await,
             None => {
-                // If no address is provided, discover the device
                 let adapter = self.get_adapter().await?;
-                let addr = self.try_find_device(&adapter).await?;
-                self.build_connection(addr).await
+                self.connect_with_adapter(&adapter).await
             }
         }
     }
+
+    /// Connects to the target device like `connect`, through an adapter
+    /// already retrieved with `get_adapter`, so the caller can keep using it
+    /// afterwards.
+    async fn connect_with_adapter(&self, adapter: &Adapter) -> Result<Stream> {
+        let addr = match self.address {
+            Some(addr) => addr,
+            // If no address is provided, look for a paired device first,
+            // then discover the device
+            None => match self.find_paired_device(adapter).await? {
+                Some(addr) => addr,
+                None => self.discover_target(adapter).await?,
+            },
+        };
+        self.connect_from(Some(adapter.address().await?), addr)
+            .await
+    }
+
+    /// Connects to the target device like `connect`, keeping the device to
+    /// monitor the signal strength of the connection while streaming:
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use cerebrust::{comm::DataReader, device::DeviceConfig};
+    /// # use futures::{StreamExt, pin_mut};
+    /// # async fn example() -> std::io::Result<()> {
+    /// let (stream, monitor) = DeviceConfig::default().connect_monitored().await?;
+    /// let mut reader = DataReader::new(stream);
+    /// let rssi = monitor.watch(Duration::from_secs(1));
+    /// pin_mut!(rssi);
+    /// loop {
+    ///     tokio::select! {
+    ///         packet = reader.poll_next() => println!("{}", packet?),
+    ///         Some(rssi) = rssi.next() => {
+    ///             reader.set_rssi(Some(rssi));
+    ///             if rssi < -80 {
+    ///                 eprintln!("Weak signal ({rssi}dBm), move closer to the adapter");
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Stream, RssiMonitor)>` - The RFCOMM stream connected to the
+    ///   target device, and the monitor of the device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if connecting fails, see
+    /// `connect`, or if the connected device cannot be retrieved from the
+    /// adapter. The adapter is retrieved even when the target device address
+    /// is known.
+    pub async fn connect_monitored(&self) -> Result<(Stream, RssiMonitor)> {
+        let adapter = self.get_adapter().await?;
+        let stream = self.connect_with_adapter(&adapter).await?;
+        let device = adapter.device(stream.peer_addr()?.addr)?;
+        Ok((stream, RssiMonitor { device }))
+    }
+
+    /// Connects to the target device like `connect`, giving up once the whole
+    /// sequence, i.e. adapter retrieval, discovery, and stream connection,
+    /// takes longer than the time budget, e.g. to bound the wait of a
+    /// connection dialog.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The time budget for the whole sequence.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Stream>` - The RFCOMM stream connected to the target device.
+    ///
+    /// # Errors
+    ///
+    /// This function will return `Error::TimedOut` if the time budget runs
+    /// out, or any error of `connect` occurring before that.
+    ///
+    /// # Cancellation
+    ///
+    /// When the time budget runs out during discovery, the discovery stream
+    /// is dropped, and this function waits up to `poll_interval` for the
+    /// adapter used to connect to stop scanning before returning, so no scan
+    /// is left running in the background. To cancel on demand instead, e.g.
+    /// from a "Cancel" button, drop the future returned by `connect`, which
+    /// stops the scan the same way, without waiting for it.
+    pub async fn connect_with_timeout(&self, timeout: Duration) -> Result<Stream> {
+        let timed_out = |_| Error::TimedOut { timeout };
+        if self.address.is_some() {
+            // No discovery to clean up after
+            return tokio::time::timeout(timeout, self.connect())
+                .await
+                .map_err(timed_out)?;
+        }
+        let deadline = tokio::time::Instant::now() + timeout;
+        let adapter = tokio::time::timeout_at(deadline, self.get_adapter())
+            .await
+            .map_err(timed_out)??;
+        match tokio::time::timeout_at(deadline, self.connect_with_adapter(&adapter)).await {
+            Ok(result) => result,
+            Err(e) => {
+                // The scan is stopped in the background once the discovery
+                // stream is dropped. Waiting for it is bounded as well, in
+                // case the adapter stops responding.
+                let wait = self.wait_discovery_stopped(&adapter);
+                if let Ok(result) = tokio::time::timeout(self.poll_interval, wait).await {
+                    result?;
+                }
+                Err(timed_out(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_device() {
+        let path = std::env::temp_dir().join(format!("cerebrust-last-{}", std::process::id()));
+        assert!(DeviceConfig::default().save_last(&path).is_err());
+
+        let address = Address::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        DeviceConfig::default()
+            .with_address(address)
+            .with_name("MyndBand".to_string())
+            .with_channel(3)
+            .save_last(&path)
+            .unwrap();
+        let config = DeviceConfig::load_last(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.address, Some(address));
+        assert_eq!(config.target_name.as_deref(), Some("MyndBand"));
+        assert_eq!(config.channel, 3);
+    }
+
+    #[test]
+    fn test_from_env() {
+        let vars = |vars: &'static [(&str, &str)]| {
+            DeviceConfig::from_vars(move |key| {
+                Ok(vars
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string()))
+            })
+        };
+        let config = vars(&[
+            ("CEREBRUST_ADAPTER", "hci1"),
+            ("CEREBRUST_DEVICE_NAME", "MindWave Mobile"),
+            ("CEREBRUST_CHANNEL", "3"),
+        ])
+        .unwrap();
+        assert_eq!(config.adapter.as_deref(), Some("hci1"));
+        assert_eq!(config.target_name.as_deref(), Some("MindWave Mobile"));
+        assert_eq!(config.channel, 3);
+
+        let config = vars(&[("CEREBRUST_ADAPTER", "")]).unwrap();
+        let default = DeviceConfig::default();
+        assert_eq!(config.adapter, default.adapter);
+        assert_eq!(config.target_name, default.target_name);
+        assert_eq!(config.channel, default.channel);
+
+        let e = vars(&[("CEREBRUST_CHANNEL", "256")]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("CEREBRUST_CHANNEL"));
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let address = Address::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        // Without a configured adapter, the Bluetooth stack picks one
+        assert_eq!(DeviceConfig::default().bind_address(Some(address)), None);
+        let config = DeviceConfig::default().with_adapter("hci1".to_string());
+        assert_eq!(
+            config.bind_address(Some(address)),
+            Some(SocketAddr::new(address, 0))
+        );
+        assert_eq!(config.bind_address(None), None);
+    }
+
+    #[test]
+    fn test_has_serial_port() {
+        let spp: Uuid = "00001101-0000-1000-8000-00805f9b34fb".parse().unwrap();
+        assert!(has_serial_port(&HashSet::from([spp])));
+        assert!(!has_serial_port(&HashSet::new()));
+    }
+
+    #[test]
+    fn test_connect_error_kinds() {
+        let e = Error::ConnectTimeout {
+            address: Address::any(),
+            timeout: Duration::from_secs(10),
+        };
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::TimedOut);
+        let e = Error::AuthenticationRequired {
+            address: Address::any(),
+        };
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::PermissionDenied);
+        let e = Error::TimedOut {
+            timeout: Duration::from_secs(30),
+        };
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::TimedOut);
+        let e = Error::AdapterPoweredOff {
+            adapter: "hci0".to_string(),
+        };
+        assert!(e.to_string().contains("hci0"));
+        assert_eq!(io::Error::from(e).kind(), io::ErrorKind::Other);
+    }
 }
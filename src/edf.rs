@@ -0,0 +1,416 @@
+//! Provides functionality to record packets into an EDF (European Data
+//! Format) file, the standard format of clinical EEG recordings. It defines
+//! the `EdfWriter` struct which records the raw wave values as an "EEG"
+//! signal, and optionally the eSense values and signal quality as separate
+//! signals. Requires the `edf` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::fs::File;
+//!
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, edf::EdfWriter};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream);
+//!     let mut edf = EdfWriter::new(File::create("session.edf")?).with_metrics(true);
+//!     for _ in 0..512 * 60 {
+//!         edf.write(&reader.poll_next().await?)?;
+//!     }
+//!     edf.finish()?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Format
+//!
+//! Each data record lasts one second and holds 512 raw wave samples, scaled
+//! to microvolts by the physical range of the signal. The optional metrics
+//! signals hold one sample per record, the latest value received at the time
+//! the record is written. The start date and time are stored in UTC.
+
+use std::{
+    io::{self, Seek, SeekFrom, Write},
+    time::SystemTime,
+};
+
+use crate::{comm::Packet, meta::SessionMeta};
+
+/// Raw wave samples per second, and per data record.
+const SAMPLE_RATE: usize = 512;
+
+/// Offset of the number of data records in the header.
+const RECORD_COUNT_OFFSET: u64 = 236;
+
+/// Describes a signal of the recording.
+struct Signal {
+    label: &'static str,
+    dimension: &'static str,
+    physical_min: &'static str,
+    physical_max: &'static str,
+    digital_min: i16,
+    digital_max: i16,
+    samples_per_record: usize,
+}
+
+/// Raw wave values, scaled by 1.8V / 4096 / 2000 = 0.2197265625uV per unit.
+const EEG: Signal = Signal {
+    label: "EEG",
+    dimension: "uV",
+    physical_min: "-7200",
+    physical_max: "7199.780",
+    digital_min: i16::MIN,
+    digital_max: i16::MAX,
+    samples_per_record: SAMPLE_RATE,
+};
+
+/// eSense values and signal quality, in the order they are recorded.
+const METRICS: [Signal; 3] = [
+    Signal {
+        label: "Attention",
+        dimension: "",
+        physical_min: "0",
+        physical_max: "255",
+        digital_min: 0,
+        digital_max: 255,
+        samples_per_record: 1,
+    },
+    Signal {
+        label: "Meditation",
+        dimension: "",
+        physical_min: "0",
+        physical_max: "255",
+        digital_min: 0,
+        digital_max: 255,
+        samples_per_record: 1,
+    },
+    Signal {
+        label: "PoorSignal",
+        dimension: "",
+        physical_min: "0",
+        physical_max: "255",
+        digital_min: 0,
+        digital_max: 255,
+        samples_per_record: 1,
+    },
+];
+
+/// Represents a writer that records packets into an EDF file.
+pub struct EdfWriter<W> {
+    /// The file to write to
+    writer: W,
+    /// Patient identification written in the header
+    patient: String,
+    /// Recording identification written in the header
+    recording: String,
+    /// Start of the recording written in the header
+    start: SystemTime,
+    /// Whether the eSense values and signal quality are recorded
+    metrics: bool,
+    /// Whether the header has been written
+    header_written: bool,
+    /// Raw wave samples of the current data record
+    samples: Vec<i16>,
+    /// Latest attention, meditation and signal quality
+    latest: [i16; 3],
+    /// Number of data records written
+    records: u64,
+}
+
+impl<W: Write + Seek> EdfWriter<W> {
+    pub fn new(writer: W) -> EdfWriter<W> {
+        EdfWriter {
+            writer,
+            patient: "X X X X".to_string(),
+            recording: "Startdate X X X X".to_string(),
+            start: SystemTime::now(),
+            metrics: false,
+            header_written: false,
+            samples: Vec::with_capacity(SAMPLE_RATE),
+            latest: [0; 3],
+            records: 0,
+        }
+    }
+
+    /// Updates the patient identification written in the header (80
+    /// characters at most). Default: "X X X X", i.e. anonymous.
+    ///
+    /// # Arguments
+    ///
+    /// * `patient` - The patient identification.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated writer.
+    pub fn with_patient(mut self, patient: String) -> Self {
+        self.patient = patient;
+        self
+    }
+
+    /// Updates the recording identification written in the header (80
+    /// characters at most).
+    ///
+    /// # Arguments
+    ///
+    /// * `recording` - The recording identification.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated writer.
+    pub fn with_recording(mut self, recording: String) -> Self {
+        self.recording = recording;
+        self
+    }
+
+    /// Updates the start of the recording written in the header. Default:
+    /// the construction time of the writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The start of the recording.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated writer.
+    pub fn with_start(mut self, start: SystemTime) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Updates the recording identification and the start of the recording
+    /// from the settings of the session. The identification lists the
+    /// settings as `key=value` pairs, truncated to 80 characters.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The settings the session was captured with.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated writer.
+    pub fn with_meta(mut self, meta: &SessionMeta) -> Self {
+        self.recording = meta
+            .entries()
+            .into_iter()
+            .filter(|(key, _)| *key != "start")
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.start = meta.start;
+        self
+    }
+
+    /// Updates whether the eSense values and signal quality are recorded as
+    /// separate signals, in addition to the raw wave values. Default: false.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Whether to record the metrics signals.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated writer.
+    pub fn with_metrics(mut self, metrics: bool) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Returns the signals of the recording, in the order they are written.
+    fn signals(&self) -> Vec<&'static Signal> {
+        let mut signals = vec![&EEG];
+        if self.metrics {
+            signals.extend(METRICS.iter());
+        }
+        signals
+    }
+
+    /// Writes the header, with an unknown number of data records.
+    fn write_header(&mut self) -> io::Result<()> {
+        let signals = self.signals();
+        let (year, month, day, hour, minute, second) = utc(self.start);
+        let mut header = Vec::with_capacity(256 * (1 + signals.len()));
+        field(&mut header, "0", 8);
+        field(&mut header, &self.patient, 80);
+        field(&mut header, &self.recording, 80);
+        field(
+            &mut header,
+            &format!("{:02}.{:02}.{:02}", day, month, year % 100),
+            8,
+        );
+        field(
+            &mut header,
+            &format!("{:02}.{:02}.{:02}", hour, minute, second),
+            8,
+        );
+        field(&mut header, &(256 * (1 + signals.len())).to_string(), 8);
+        field(&mut header, "", 44);
+        field(&mut header, "-1", 8);
+        field(&mut header, "1", 8);
+        field(&mut header, &signals.len().to_string(), 4);
+        // Signal fields are grouped by field, not by signal
+        signals.iter().for_each(|s| field(&mut header, s.label, 16));
+        signals.iter().for_each(|_| field(&mut header, "", 80));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, s.dimension, 8));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, s.physical_min, 8));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, s.physical_max, 8));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, &s.digital_min.to_string(), 8));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, &s.digital_max.to_string(), 8));
+        signals.iter().for_each(|_| field(&mut header, "", 80));
+        signals
+            .iter()
+            .for_each(|s| field(&mut header, &s.samples_per_record.to_string(), 8));
+        signals.iter().for_each(|_| field(&mut header, "", 32));
+        self.writer.write_all(&header)?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    /// Writes the current data record, padding missing samples with zeros.
+    fn write_record(&mut self) -> io::Result<()> {
+        self.samples.resize(SAMPLE_RATE, 0);
+        let mut record = Vec::with_capacity(2 * (SAMPLE_RATE + self.latest.len()));
+        self.samples
+            .iter()
+            .for_each(|x| record.extend(x.to_le_bytes()));
+        if self.metrics {
+            self.latest
+                .iter()
+                .for_each(|x| record.extend(x.to_le_bytes()));
+        }
+        self.writer.write_all(&record)?;
+        self.samples.clear();
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Records a packet. Raw wave values are buffered until a full data
+    /// record is available; eSense values and signal quality update the
+    /// metrics signals of the next data record written.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to record.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the file fails.
+    pub fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        let metrics = [packet.attention, packet.meditation, packet.poor_signal];
+        for (latest, value) in self.latest.iter_mut().zip(metrics) {
+            if let Some(value) = value {
+                *latest = value as i16;
+            }
+        }
+        if let Some(raw_wave) = packet.raw_wave {
+            self.samples.push(raw_wave);
+            if self.samples.len() == SAMPLE_RATE {
+                self.write_record()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the last, partial data record padded with zeros, and updates
+    /// the number of data records in the header.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<W>` - The underlying file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing to the file fails.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        if !self.samples.is_empty() {
+            self.write_record()?;
+        }
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(RECORD_COUNT_OFFSET))?;
+        let mut count = Vec::with_capacity(8);
+        field(&mut count, &self.records.to_string(), 8);
+        self.writer.write_all(&count)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Appends an ASCII header field, left-aligned and padded with spaces.
+fn field(header: &mut Vec<u8>, value: &str, width: usize) {
+    let mut bytes: Vec<u8> = value
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'_' })
+        .take(width)
+        .collect();
+    bytes.resize(width, b' ');
+    header.extend(bytes);
+}
+
+/// Converts a time into UTC (year, month, day, hour, minute, second).
+fn utc(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u32);
+    // Civil date from days since epoch, see https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_edf_layout() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut edf = EdfWriter::new(Cursor::new(Vec::new()))
+            .with_start(start)
+            .with_metrics(true);
+        let raw = Packet {
+            raw_wave: Some(-2),
+            ..Default::default()
+        };
+        for _ in 0..SAMPLE_RATE + 10 {
+            edf.write(&raw).unwrap();
+        }
+        let bytes = edf.finish().unwrap().into_inner();
+
+        let header_len = 256 * 5;
+        assert_eq!(bytes.len(), header_len + 2 * 2 * (SAMPLE_RATE + 3));
+        assert_eq!(&bytes[168..184], b"14.11.2322.13.20");
+        assert_eq!(&bytes[184..192], b"1280    ");
+        assert_eq!(&bytes[236..244], b"2       ");
+        assert_eq!(&bytes[256..272], b"EEG             ");
+        assert_eq!(&bytes[header_len..header_len + 2], &(-2i16).to_le_bytes());
+    }
+}
@@ -0,0 +1,223 @@
+//! Provides an adapter to process raw wave values in windows rather than
+//! sample by sample. It defines the `RawWaveFramer` struct which buffers the
+//! raw wave values of a packet stream and yields them in frames of a fixed
+//! number of samples, optionally overlapping, e.g. 512 samples for a
+//! one-second FFT.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, framer::RawWaveFramer};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     // One second of samples, every half second
+//!     let mut frames = RawWaveFramer::new(DataReader::new(stream), 512).with_overlap(256);
+//!     while let Some(frame) = frames.next().await {
+//!         let frame = frame?;
+//!         println!("{} samples, first {}", frame.len(), frame[0]);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Samples
+//!
+//! Only packets with a raw wave value contribute a sample; packets without
+//! one, e.g. eSense values and EEG power spectrum, are skipped, so they do
+//! not leave holes in a frame. Reduced-precision values (`raw_wave8`) are not
+//! mixed in. Errors are passed through without discarding the buffered
+//! samples. Once the stream ends, the samples that did not fill a frame are
+//! dropped, unless `RawWaveFramer::with_partial` is enabled.
+
+use std::{
+    io::Error,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::Stream;
+
+use crate::comm::Packet;
+
+/// Represents an adapter that yields the raw wave values of a packet stream
+/// in frames of a fixed number of samples.
+pub struct RawWaveFramer<S> {
+    /// The packet stream to take the samples from
+    stream: S,
+    /// Number of samples per frame
+    size: usize,
+    /// Number of samples between the starts of two consecutive frames
+    hop: usize,
+    /// Buffered samples, oldest first
+    samples: Vec<i16>,
+    /// Number of buffered samples not yielded in any frame yet
+    fresh: usize,
+    /// Whether the last, incomplete frame is yielded at the end of the stream
+    partial: bool,
+    /// Whether the wrapped stream has ended
+    ended: bool,
+}
+
+impl<S> RawWaveFramer<S> {
+    /// # Arguments
+    ///
+    /// * `stream` - The packet stream to take the samples from.
+    /// * `size` - The number of samples per frame, e.g. 512 for one second.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `size` is zero.
+    pub fn new(stream: S, size: usize) -> RawWaveFramer<S> {
+        assert!(size > 0, "Frame size must be non-zero");
+        RawWaveFramer {
+            stream,
+            size,
+            hop: size,
+            samples: Vec::with_capacity(size),
+            fresh: 0,
+            partial: false,
+            ended: false,
+        }
+    }
+
+    /// Updates the number of samples shared by two consecutive frames.
+    /// Default: 0, i.e. frames do not overlap.
+    ///
+    /// # Arguments
+    ///
+    /// * `overlap` - The number of samples at the end of a frame repeated at
+    ///   the start of the next one. Must be less than the frame size.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `overlap` is not less than the frame size.
+    pub fn with_overlap(mut self, overlap: usize) -> Self {
+        assert!(
+            overlap < self.size,
+            "Overlap must be less than the frame size"
+        );
+        self.hop = self.size - overlap;
+        self
+    }
+
+    /// Updates whether the samples left at the end of the stream are yielded
+    /// as a last, shorter frame, if any of them was not yielded yet. Default:
+    /// `false`.
+    ///
+    /// # Arguments
+    ///
+    /// * `partial` - Whether to yield the last, incomplete frame.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    pub fn with_partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    /// Consumes the adapter, returning the wrapped packet stream. Buffered
+    /// samples are lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Buffers a sample, returning a frame once the buffer is full.
+    fn push(&mut self, sample: i16) -> Option<Vec<i16>> {
+        self.samples.push(sample);
+        self.fresh += 1;
+        if self.samples.len() < self.size {
+            return None;
+        }
+        let frame = self.samples.clone();
+        self.samples.drain(..self.hop);
+        self.fresh = 0;
+        Some(frame)
+    }
+}
+
+impl<S, E> Stream for RawWaveFramer<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Vec<i16>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.ended {
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(packet)) => {
+                    if let Some(frame) = packet.raw_wave.and_then(|x| this.push(x)) {
+                        return Poll::Ready(Some(Ok(frame)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => {
+                    this.ended = true;
+                    let rest = std::mem::take(&mut this.samples);
+                    if this.partial && this.fresh > 0 {
+                        return Poll::Ready(Some(Ok(rest)));
+                    }
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, executor::block_on, stream};
+
+    use super::*;
+    use crate::comm::Power;
+
+    #[test]
+    fn test_raw_wave_framer() {
+        let raw = |raw_wave| Packet {
+            raw_wave: Some(raw_wave),
+            ..Default::default()
+        };
+        let power = Packet {
+            eeg_power: Some(Power::default()),
+            ..Default::default()
+        };
+        let mut packets: Vec<_> = (1..=10).map(raw).collect();
+        packets.insert(3, power);
+        let packets = || stream::iter(packets.clone().into_iter().map(Ok::<_, Error>));
+        let collect = |framer: RawWaveFramer<_>| -> Vec<Vec<i16>> {
+            block_on(framer.map(Result::unwrap).collect())
+        };
+
+        let frames = collect(RawWaveFramer::new(packets(), 4).with_overlap(2));
+        assert_eq!(
+            frames,
+            vec![
+                vec![1, 2, 3, 4],
+                vec![3, 4, 5, 6],
+                vec![5, 6, 7, 8],
+                vec![7, 8, 9, 10]
+            ]
+        );
+        // All samples were yielded, so there is no partial frame
+        let frames = collect(
+            RawWaveFramer::new(packets(), 4)
+                .with_overlap(2)
+                .with_partial(true),
+        );
+        assert_eq!(frames.len(), 4);
+
+        let frames = collect(RawWaveFramer::new(packets(), 4));
+        assert_eq!(frames, vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8]]);
+        let frames = collect(RawWaveFramer::new(packets(), 4).with_partial(true));
+        assert_eq!(frames.last(), Some(&vec![9, 10]));
+    }
+}
@@ -0,0 +1,179 @@
+//! Provides functionality to re-encode a stream of packets as newline
+//! delimited JSON (NDJSON). It defines the `JsonEncodeStream` struct which
+//! wraps a packet stream and implements `AsyncRead`, so the encoded bytes can
+//! be piped into any byte sink. Requires the `serde` feature.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, json::JsonEncodeStream};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut json = JsonEncodeStream::new(DataReader::new(stream));
+//!     // Any `AsyncWrite` sink works, e.g. a socket or a child process stdin
+//!     let mut sink = Vec::new();
+//!     tokio::io::copy(&mut json, &mut sink).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Errors
+//!
+//! Errors yielded by the wrapped stream are returned from the read call that
+//! would have returned the failed packet. The end of the wrapped stream is
+//! reported as end-of-file.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::Stream;
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use crate::{comm::Packet, meta::SessionMeta};
+
+/// Represents an adapter that encodes each packet of a stream as a single
+/// line of JSON, readable through `AsyncRead` and `AsyncBufRead`.
+pub struct JsonEncodeStream<S> {
+    /// The packet stream to encode
+    stream: S,
+    /// The encoded line currently being read
+    line: Vec<u8>,
+    /// Position of the next byte to read in `line`
+    pos: usize,
+    /// Whether the packet stream has ended
+    ended: bool,
+}
+
+impl<S> JsonEncodeStream<S> {
+    pub fn new(stream: S) -> JsonEncodeStream<S> {
+        JsonEncodeStream {
+            stream,
+            line: Vec::new(),
+            pos: 0,
+            ended: false,
+        }
+    }
+
+    /// Writes the settings of the session as a first line, of the form
+    /// `{"meta": {...}}`, before the packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The settings the session was captured with.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    pub fn with_meta(mut self, meta: &SessionMeta) -> Self {
+        self.line.clear();
+        self.pos = 0;
+        serde_json::to_writer(&mut self.line, &serde_json::json!({ "meta": meta }))
+            .expect("Session settings are always serializable");
+        self.line.push(b'\n');
+        self
+    }
+
+    /// Consumes the adapter, returning the wrapped packet stream. Bytes of
+    /// the current line that were not read yet are lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> AsyncBufRead for JsonEncodeStream<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<io::Error>,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos == this.line.len() && !this.ended {
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(packet)) => {
+                    this.line.clear();
+                    this.pos = 0;
+                    serde_json::to_writer(&mut this.line, &packet)?;
+                    this.line.push(b'\n');
+                }
+                Some(Err(e)) => return Poll::Ready(Err(e.into())),
+                None => this.ended = true,
+            }
+        }
+        Poll::Ready(Ok(&this.line[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = (this.pos + amt).min(this.line.len());
+    }
+}
+
+impl<S, E> AsyncRead for JsonEncodeStream<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<io::Error>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = data.len().min(buf.remaining());
+        buf.put_slice(&data[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    /// Reads with a small buffer until end-of-file or an error, returning the
+    /// bytes read so far along with the error, if any.
+    async fn read_small<R: AsyncRead + Unpin>(reader: &mut R) -> (String, Option<io::Error>) {
+        let mut buf = [0; 8];
+        let mut read = Vec::new();
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => return (String::from_utf8(read).unwrap(), None),
+                Ok(n) => read.extend_from_slice(&buf[..n]),
+                Err(e) => return (String::from_utf8(read).unwrap(), Some(e)),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_encode_stream() {
+        let packet = |attention| Packet {
+            attention: Some(attention),
+            ..Default::default()
+        };
+        let packets = [
+            Ok(packet(50)),
+            Ok(packet(60)),
+            Err(io::Error::other("Connection lost")),
+            Ok(packet(70)),
+        ];
+        let mut json = JsonEncodeStream::new(stream::iter(packets));
+
+        // Lines longer than the buffer are read in several calls
+        let (read, e) = read_small(&mut json).await;
+        assert_eq!(read, "{\"attention\":50}\n{\"attention\":60}\n");
+        assert_eq!(e.unwrap().to_string(), "Connection lost");
+        let (read, e) = read_small(&mut json).await;
+        assert_eq!(read, "{\"attention\":70}\n");
+        assert!(e.is_none());
+        // End-of-file is reported again on further reads
+        assert_eq!(json.read(&mut [0; 8]).await.unwrap(), 0);
+    }
+}
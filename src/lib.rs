@@ -1,13 +1,52 @@
 //! cerebrust is a library for interfacing with NeuroSky devices over Bluetooth.
 //! It provides functionality to configure and connect to a NeuroSky device using
-//! Bluetooth, as well as to read and parse data packets from the data stream.
+//! Bluetooth, as well as to read and parse data packets from the data stream,
+//! and to send configuration commands to the device.
 
+/// Emits a diagnostic through the `log` facade at the given level with the
+/// `log` feature, or on stderr otherwise.
+macro_rules! diagnostic {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        eprintln!($($arg)*);
+    }};
+}
+
+pub mod blocking;
+pub mod channel;
+pub mod classify;
+pub mod coalesce;
 pub mod comm;
+pub mod command;
+#[cfg(feature = "csv")]
+pub mod csv;
 pub mod device;
+#[cfg(feature = "edf")]
+pub mod edf;
+pub mod framer;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod median;
+pub mod meta;
+pub mod net;
+pub mod pipeline;
+pub mod probe;
+pub mod quality;
+pub mod reconnect;
+pub mod smooth;
+pub mod summary;
 
 // Re-export for convenience
-pub use comm::{DataReader, Packet, PacketVariant};
-pub use device::DeviceConfig;
+pub use channel::OverflowPolicy;
+pub use comm::{
+    Band, CommError, DataReader, LockThresholds, Packet, PacketVariant, Power, ReaderStats,
+    Snapshot,
+};
+pub use command::{Command, DeviceWriter};
+pub use device::{DeviceConfig, DeviceInfo, Error as DeviceError, RssiMonitor};
+pub use quality::{ContactTracker, ContactTrend, SignalQuality};
 
 #[cfg(test)]
 mod tests {
@@ -29,14 +68,38 @@ mod tests {
             .connect()
             .await
             .expect("Failed to build RFCOMM stream");
-        println!("Local address: {:?}", stream.as_ref().local_addr().unwrap());
-        println!("Remote address: {:?}", stream.peer_addr().unwrap());
-        println!("Security: {:?}", stream.as_ref().security().unwrap());
         let mut data_reader = DataReader::new(stream);
+        println!("Local address: {:?}", data_reader.local_addr().unwrap());
+        println!("Remote address: {:?}", data_reader.peer_addr().unwrap());
+        println!("Security: {:?}", data_reader.security().unwrap());
         let packet = data_reader
             .poll_next()
             .await
             .expect("Failed to poll next packet");
         println!("{:?}", packet);
+        drop(data_reader);
+
+        // Connecting again must not leave the adapter scanning, as a lingering
+        // scan makes the second connection flaky. The device is paired by
+        // now, so `connect` would skip discovery: take the scan path
+        // explicitly instead.
+        let adapter = config.get_adapter().await.expect("Failed to get adapter");
+        let addr = config
+            .discover_target(&adapter)
+            .await
+            .expect("Failed to discover device");
+        assert!(
+            !adapter.is_discovering().await.unwrap(),
+            "Adapter is still scanning after discovery"
+        );
+        let stream = config
+            .build_connection(addr)
+            .await
+            .expect("Failed to rebuild RFCOMM stream");
+        // The connection must go out through the configured adapter
+        assert_eq!(
+            stream.as_ref().local_addr().unwrap().addr,
+            adapter.address().await.unwrap()
+        );
     }
 }
@@ -0,0 +1,162 @@
+//! Provides an adapter to reject outliers in the EEG power spectrum. It
+//! defines the `MedianFilter` struct which replaces the EEG power of each
+//! packet with the median of the last readings, band by band, so a single
+//! corrupted frame whose checksum happens to be valid does not show up as a
+//! spike.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, median::MedianFilter};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut packets = MedianFilter::new(DataReader::new(stream)).with_window(5);
+//!     while let Some(packet) = packets.next().await {
+//!         if let Some(eeg_power) = packet?.eeg_power {
+//!             println!("{:?}", eeg_power);
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Latency
+//!
+//! A median rejects a spike lasting less than half of the window, at the
+//! cost of delaying genuine changes by about as much. With the default window
+//! of 3 readings, i.e. 3 seconds, single-reading spikes are rejected and a
+//! change shows up one second late. Packets are never dropped or reordered,
+//! and the adapter follows the same contract as the `Stream` implementation
+//! of `DataReader`.
+
+use std::{
+    collections::VecDeque,
+    io::Error,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::Stream;
+
+use crate::comm::{Band, Packet, Power};
+
+/// Represents an adapter that replaces the EEG power of each packet with the
+/// median of the last readings, band by band.
+pub struct MedianFilter<S> {
+    /// The packet stream to filter
+    stream: S,
+    /// Number of readings the median is taken over
+    window: usize,
+    /// Last readings, oldest first
+    readings: VecDeque<Power>,
+}
+
+impl<S> MedianFilter<S> {
+    pub fn new(stream: S) -> MedianFilter<S> {
+        MedianFilter {
+            stream,
+            window: 3,
+            readings: VecDeque::with_capacity(3),
+        }
+    }
+
+    /// Updates the number of readings the median is taken over. Default: 3.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The number of readings, preferably odd. Must be non-zero.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `window` is zero.
+    pub fn with_window(mut self, window: usize) -> Self {
+        assert!(window > 0, "Window must be non-zero");
+        self.window = window;
+        self.readings = VecDeque::with_capacity(window);
+        self
+    }
+
+    /// Consumes the adapter, returning the wrapped packet stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Feeds a reading, returning the median of each band over the window.
+    /// Until the window is full, the median is taken over the readings so
+    /// far. With an even number of readings, the lower median is used, so a
+    /// spike is rejected as soon as the second reading.
+    fn filter(&mut self, power: Power) -> Power {
+        if self.readings.len() == self.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(power);
+        let mut medians = [0; 8];
+        let mut band = Vec::with_capacity(self.readings.len());
+        for (i, median) in medians.iter_mut().enumerate() {
+            band.clear();
+            band.extend(self.readings.iter().map(|power| power.as_array()[i]));
+            band.sort_unstable();
+            *median = band[(band.len() - 1) / 2];
+        }
+        Power::from_map(&Band::ALL.into_iter().zip(medians).collect())
+    }
+}
+
+impl<S, E> Stream for MedianFilter<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Packet, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = ready!(Pin::new(&mut this.stream).poll_next(cx));
+        Poll::Ready(item.map(|item| {
+            item.map_err(Into::into).map(|mut packet| {
+                packet.eeg_power = packet.eeg_power.map(|power| this.filter(power));
+                packet
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, executor::block_on, stream};
+
+    use super::*;
+
+    #[test]
+    fn test_median_filter() {
+        let power = |delta| Packet {
+            eeg_power: Some(Power {
+                delta,
+                theta: 2 * delta,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let raw = Packet {
+            raw_wave: Some(7),
+            ..Default::default()
+        };
+        let packets = [power(10), raw, power(1000), power(12), power(11)].map(Ok::<_, Error>);
+        let filtered: Vec<_> = block_on(MedianFilter::new(stream::iter(packets)).collect());
+        let delta: Vec<_> = filtered
+            .iter()
+            .map(|p| p.as_ref().unwrap().eeg_power.map(|power| power.delta))
+            .collect();
+        assert_eq!(delta, vec![Some(10), None, Some(10), Some(12), Some(12)]);
+        let theta = filtered[4].as_ref().unwrap().eeg_power.unwrap().theta;
+        assert_eq!(theta, 24);
+        assert_eq!(filtered[1].as_ref().unwrap().raw_wave, Some(7));
+    }
+}
@@ -0,0 +1,176 @@
+//! Provides functionality to describe how a recording was captured. It
+//! defines the `SessionMeta` struct which holds the connection settings and
+//! start time of a session, and which the recorders write as a header, so a
+//! recording can be interpreted later without separate notes.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{device::DeviceConfig, meta::SessionMeta};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let config = DeviceConfig::default().with_name("MyndBand".to_string());
+//!     let stream = config.connect().await?;
+//!     let meta = SessionMeta::new(&config).with_address(stream.peer_addr()?.addr);
+//!     for (key, value) in meta.entries() {
+//!         println!("{}: {}", key, value);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Recorders
+//!
+//! - `Pipeline::record_csv_with_meta` writes one `# key: value` comment line
+//!   per entry before the CSV header, and `CsvRecorder::with_meta` does the
+//!   same (both require the `csv` feature).
+//! - `JsonEncodeStream::with_meta` writes a `{"meta": {...}}` line before the
+//!   packets (requires the `serde` feature).
+//! - `EdfWriter::with_meta` writes the entries into the recording
+//!   identification, and the start time into the header (requires the `edf`
+//!   feature).
+
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bluer::Address;
+
+use crate::device::DeviceConfig;
+
+/// Represents the settings a session was captured with.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionMeta {
+    /// Name of the Bluetooth adapter, `None` for the default adapter
+    pub adapter: Option<String>,
+    /// Name of the target device
+    pub device_name: Option<String>,
+    /// Address of the target device, if known
+    pub address: Option<String>,
+    /// RFCOMM channel
+    pub channel: u8,
+    /// Output mode command sent after connecting, e.g. "RawOutput"
+    pub output_mode: Option<String>,
+    /// Start of the session
+    pub start: SystemTime,
+}
+
+impl SessionMeta {
+    /// Describes a session captured with a configuration, starting now.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration the device was connected with.
+    ///
+    /// # Returns
+    ///
+    /// * `SessionMeta` - The description of the session.
+    pub fn new(config: &DeviceConfig) -> SessionMeta {
+        SessionMeta {
+            adapter: config.adapter.clone(),
+            device_name: config.target_name.clone(),
+            address: config.address.map(|x| x.to_string()),
+            channel: config.channel,
+            output_mode: config.initial_command.map(|x| format!("{:?}", x)),
+            start: SystemTime::now(),
+        }
+    }
+
+    /// Updates the address of the target device, e.g. the one resolved by
+    /// discovery.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address of the target device.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated description.
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = Some(address.to_string());
+        self
+    }
+
+    /// Updates the start of the session. Default: the construction time.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The start of the session.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated description.
+    pub fn with_start(mut self, start: SystemTime) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Returns the settings as key-value pairs, leaving out unknown ones. The
+    /// start is given in seconds since the Unix epoch.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let start = self
+            .start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        [
+            ("adapter", self.adapter.clone()),
+            ("device_name", self.device_name.clone()),
+            ("address", self.address.clone()),
+            ("channel", Some(self.channel.to_string())),
+            ("output_mode", self.output_mode.clone()),
+            ("start", Some(format!("{:.3}", start))),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+    }
+
+    /// Writes the settings as `# key: value` comment lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The sink to write the lines to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if writing fails.
+    pub fn write_comments<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (key, value) in self.entries() {
+            writeln!(writer, "# {}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::command::Command;
+
+    #[test]
+    fn test_session_meta() {
+        let config = DeviceConfig::default()
+            .with_name("MyndBand".to_string())
+            .with_channel(3)
+            .with_initial_command(Command::RawOutput);
+        let meta = SessionMeta::new(&config)
+            .with_address(Address::new([0x00, 0x11, 0x22, 0x33, 0x44, 0x55]))
+            .with_start(UNIX_EPOCH + Duration::from_millis(1500));
+        let mut comments = Vec::new();
+        meta.write_comments(&mut comments).unwrap();
+        assert_eq!(
+            String::from_utf8(comments).unwrap(),
+            "# device_name: MyndBand\n\
+             # address: 00:11:22:33:44:55\n\
+             # channel: 3\n\
+             # output_mode: RawOutput\n\
+             # start: 1.500\n"
+        );
+    }
+}
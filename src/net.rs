@@ -0,0 +1,322 @@
+//! Provides functionality to split acquisition from visualization across a
+//! network. It defines the `NetworkForwarder` struct which sends packets to
+//! another machine over UDP or TCP, and the `NetworkReader` struct which
+//! receives them there as a packet stream.
+//!
+//! # Example
+//!
+//! On the machine with the Bluetooth adapter:
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, net::NetworkForwarder};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut forwarder = NetworkForwarder::udp("192.168.1.20:5005").await?;
+//!     forwarder.forward(DataReader::new(stream)).await
+//! }
+//! ```
+//!
+//! On the visualization machine:
+//!
+//! ```rust,no_run
+//! use cerebrust::net::NetworkReader;
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let mut packets = NetworkReader::udp("0.0.0.0:5005").await?;
+//!     while let Some(packet) = packets.next().await {
+//!         println!("{:?}", packet?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Wire format
+//!
+//! Packets are sent as ThinkGear frames (see `Packet::to_frame`), the same
+//! compact format the device itself sends, with one frame per UDP datagram.
+//! Over UDP, lost or reordered datagrams are not detected; use TCP when every
+//! packet matters. Over TCP, the forwarder connects to the reader, which
+//! accepts a single connection.
+
+use std::{
+    io::{Error, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::{Stream, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncWriteExt, ReadBuf},
+    net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket, lookup_host},
+};
+
+use crate::comm::{Decoder, Frame, Packet};
+
+/// Connection a forwarder sends packets over.
+enum Sink {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Represents a sender of packets to a `NetworkReader` on another machine.
+pub struct NetworkForwarder {
+    /// Connection to the reader
+    sink: Sink,
+}
+
+impl NetworkForwarder {
+    /// Prepares to send packets as UDP datagrams to a reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The address the reader is bound to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the address cannot be resolved,
+    /// or if no local socket can be bound.
+    pub async fn udp(target: impl ToSocketAddrs) -> Result<NetworkForwarder, Error> {
+        let target = lookup_host(target)
+            .await?
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No address to forward to"))?;
+        let local = match target {
+            std::net::SocketAddr::V4(_) => "0.0.0.0:0",
+            std::net::SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(target).await?;
+        Ok(NetworkForwarder {
+            sink: Sink::Udp(socket),
+        })
+    }
+
+    /// Connects to a reader listening for TCP connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The address the reader listens on.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the connection fails.
+    pub async fn tcp(target: impl ToSocketAddrs) -> Result<NetworkForwarder, Error> {
+        let stream = TcpStream::connect(target).await?;
+        // Packets are small and latency matters more than throughput
+        stream.set_nodelay(true)?;
+        Ok(NetworkForwarder {
+            sink: Sink::Tcp(stream),
+        })
+    }
+
+    /// Sends a single packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet to send.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if sending fails, e.g. the reader
+    /// closed the TCP connection.
+    pub async fn send(&mut self, packet: &Packet) -> Result<(), Error> {
+        let frame = packet.to_frame();
+        match &mut self.sink {
+            Sink::Udp(socket) => socket.send(&frame).await.map(|_| ()),
+            Sink::Tcp(stream) => stream.write_all(&frame).await,
+        }
+    }
+
+    /// Sends every packet of a stream, until it ends.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The packets to send, e.g. a `DataReader`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the first error of the stream, or of
+    /// sending a packet.
+    pub async fn forward<S, E>(&mut self, mut stream: S) -> Result<(), Error>
+    where
+        S: Stream<Item = Result<Packet, E>> + Unpin,
+        E: Into<Error>,
+    {
+        while let Some(packet) = stream.next().await {
+            self.send(&packet.map_err(Into::into)?).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Connection a reader receives packets from.
+enum Source {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+/// Represents a stream of packets sent by a `NetworkForwarder` on another
+/// machine. It follows the same contract as the `Stream` implementation of
+/// `DataReader`; over UDP, the stream never ends on its own.
+pub struct NetworkReader {
+    /// Connection to the forwarder
+    source: Source,
+    /// Framing decoder fed with the bytes received
+    decoder: Decoder,
+    /// Bytes received but not decoded yet
+    buffer: Box<[u8]>,
+    /// Position of the next byte to decode in `buffer`
+    pos: usize,
+    /// Number of valid bytes in `buffer`
+    filled: usize,
+    /// Whether the stream has ended
+    ended: bool,
+}
+
+impl NetworkReader {
+    fn new(source: Source) -> NetworkReader {
+        NetworkReader {
+            source,
+            decoder: Decoder::new(),
+            // Large enough for any UDP datagram
+            buffer: vec![0; u16::MAX as usize].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            ended: false,
+        }
+    }
+
+    /// Receives packets sent as UDP datagrams.
+    ///
+    /// # Arguments
+    ///
+    /// * `bind` - The local address to receive on.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the socket cannot be bound.
+    pub async fn udp(bind: impl ToSocketAddrs) -> Result<NetworkReader, Error> {
+        Ok(NetworkReader::new(Source::Udp(
+            UdpSocket::bind(bind).await?,
+        )))
+    }
+
+    /// Listens for a TCP connection from a forwarder, and receives packets
+    /// from the first one accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `bind` - The local address to listen on.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the listener cannot be bound,
+    /// or if accepting the connection fails.
+    pub async fn tcp(bind: impl ToSocketAddrs) -> Result<NetworkReader, Error> {
+        NetworkReader::accept(&TcpListener::bind(bind).await?).await
+    }
+
+    /// Receives packets from the next TCP connection accepted by a listener,
+    /// e.g. to let the forwarder reconnect.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listener the forwarder connects to.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if accepting the connection fails.
+    pub async fn accept(listener: &TcpListener) -> Result<NetworkReader, Error> {
+        let (stream, _) = listener.accept().await?;
+        Ok(NetworkReader::new(Source::Tcp(stream)))
+    }
+}
+
+impl Stream for NetworkReader {
+    type Item = Result<Packet, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while !this.ended {
+            while this.pos < this.filled {
+                let byte = this.buffer[this.pos];
+                this.pos += 1;
+                if let Some(Frame::Packet(packet)) = this.decoder.push(byte) {
+                    return Poll::Ready(Some(Ok(packet)));
+                }
+            }
+            let mut buf = ReadBuf::new(&mut this.buffer);
+            let result = match &mut this.source {
+                Source::Udp(socket) => socket.poll_recv(cx, &mut buf),
+                Source::Tcp(stream) => Pin::new(stream).poll_read(cx, &mut buf),
+            };
+            if let Err(e) = ready!(result) {
+                this.ended = true;
+                return Poll::Ready(Some(Err(e)));
+            }
+            this.pos = 0;
+            this.filled = buf.filled().len();
+            if this.filled == 0 && matches!(this.source, Source::Tcp(_)) {
+                this.ended = true;
+                if !this.decoder.is_idle() {
+                    return Poll::Ready(Some(Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "Stream ended in the middle of a packet",
+                    ))));
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forward() {
+        let packets = [
+            Packet {
+                raw_wave: Some(-7),
+                ..Default::default()
+            },
+            Packet {
+                poor_signal: Some(0),
+                attention: Some(60),
+                ..Default::default()
+            },
+        ];
+
+        let mut reader = NetworkReader::udp("127.0.0.1:0").await.unwrap();
+        let Source::Udp(socket) = &reader.source else {
+            unreachable!()
+        };
+        let mut forwarder = NetworkForwarder::udp(socket.local_addr().unwrap())
+            .await
+            .unwrap();
+        for packet in &packets {
+            forwarder.send(packet).await.unwrap();
+            assert_eq!(reader.next().await.unwrap().unwrap(), *packet);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (reader, forwarder) = tokio::join!(
+            NetworkReader::accept(&listener),
+            NetworkForwarder::tcp(addr)
+        );
+        let mut forwarder = forwarder.unwrap();
+        forwarder
+            .forward(futures::stream::iter(packets.clone().map(Ok::<_, Error>)))
+            .await
+            .unwrap();
+        drop(forwarder);
+        let received: Vec<_> = reader.unwrap().map(|p| p.unwrap()).collect().await;
+        assert_eq!(received, packets);
+    }
+}
@@ -0,0 +1,313 @@
+//! Provides a builder to assemble the processing steps applied to a packet
+//! stream. It defines the `Pipeline` struct which collects the steps, and the
+//! `Processed` struct which drives them as a single packet stream.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, pipeline::Pipeline};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut packets = Pipeline::from(DataReader::new(stream))
+//!         .filter_signal(0)
+//!         .smooth_esense(0.3)
+//!         .build();
+//!     while let Some(packet) = packets.next().await {
+//!         println!("{:?}", packet?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Order of the steps
+//!
+//! Steps are applied in the order they are added, so a recording step only
+//! sees the packets that passed the steps before it, with their values as
+//! modified by them, e.g. `record_csv` (requires the `csv` feature) after
+//! `smooth_esense` records the smoothed values. The resulting stream follows
+//! the same contract as the `Stream` implementation of `DataReader`.
+
+#[cfg(feature = "csv")]
+use std::io::Write;
+use std::{
+    io::Error,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures::Stream;
+
+use crate::{comm::Packet, smooth::Averages};
+#[cfg(feature = "csv")]
+use crate::{csv::CsvRecorder, meta::SessionMeta};
+
+/// Represents a single processing step of a pipeline.
+trait Step: Send {
+    /// Processes a packet, returning `None` to drop it.
+    fn process(&mut self, packet: Packet) -> Result<Option<Packet>, Error>;
+}
+
+/// Drops packets whose signal quality is worse than a threshold.
+struct FilterSignal {
+    max_poor_signal: u8,
+}
+
+impl Step for FilterSignal {
+    fn process(&mut self, packet: Packet) -> Result<Option<Packet>, Error> {
+        match packet.poor_signal {
+            Some(poor_signal) if poor_signal > self.max_poor_signal => Ok(None),
+            _ => Ok(Some(packet)),
+        }
+    }
+}
+
+/// Smooths the eSense values with an exponential moving average, see
+/// `Smoother`.
+struct SmoothEsense(Averages);
+
+impl Step for SmoothEsense {
+    fn process(&mut self, mut packet: Packet) -> Result<Option<Packet>, Error> {
+        self.0.smooth(&mut packet);
+        Ok(Some(packet))
+    }
+}
+
+/// Records each packet as a row of CSV, see `CsvRecorder`.
+#[cfg(feature = "csv")]
+struct RecordCsv<W>(CsvRecorder<W>);
+
+#[cfg(feature = "csv")]
+impl<W: Write + Send> Step for RecordCsv<W> {
+    fn process(&mut self, packet: Packet) -> Result<Option<Packet>, Error> {
+        self.0.write(&packet)?;
+        Ok(Some(packet))
+    }
+}
+
+/// Represents a builder of the processing steps applied to a packet stream.
+pub struct Pipeline<S> {
+    /// The packet stream to process
+    stream: S,
+    /// Processing steps, in the order they are applied
+    steps: Vec<Box<dyn Step>>,
+}
+
+impl<S> From<S> for Pipeline<S> {
+    fn from(stream: S) -> Self {
+        Pipeline {
+            stream,
+            steps: Vec::new(),
+        }
+    }
+}
+
+impl<S> Pipeline<S> {
+    /// Drops packets whose signal quality is worse than `max_poor_signal`.
+    /// Packets without a signal quality, e.g. raw wave packets, are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_poor_signal` - The worst signal quality kept (0 ~ 255), 0 meaning a perfect signal.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated pipeline.
+    pub fn filter_signal(mut self, max_poor_signal: u8) -> Self {
+        self.steps.push(Box::new(FilterSignal { max_poor_signal }));
+        self
+    }
+
+    /// Smooths the attention and meditation values with an exponential
+    /// moving average, restarted after a dropout or a gap like `Smoother`,
+    /// with its default thresholds.
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha` - The weight of a new value, in the range (0, 1].
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated pipeline.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `alpha` is not in the range (0, 1].
+    pub fn smooth_esense(mut self, alpha: f64) -> Self {
+        self.steps
+            .push(Box::new(SmoothEsense(Averages::exponential(alpha))));
+        self
+    }
+
+    /// Records each packet as a row of CSV, with a header row first, in the
+    /// format of `CsvRecorder`. Requires the `csv` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The sink to write the CSV to, preferably buffered.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated pipeline.
+    #[cfg(feature = "csv")]
+    pub fn record_csv<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.steps
+            .push(Box::new(RecordCsv(CsvRecorder::new(writer))));
+        self
+    }
+
+    /// Records each packet as a row of CSV, like `record_csv`, with the
+    /// settings of the session written as `# key: value` comment lines
+    /// before the header. Requires the `csv` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The sink to write the CSV to, preferably buffered.
+    /// * `meta` - The settings the session was captured with.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated pipeline.
+    #[cfg(feature = "csv")]
+    pub fn record_csv_with_meta<W: Write + Send + 'static>(
+        mut self,
+        writer: W,
+        meta: SessionMeta,
+    ) -> Self {
+        let recorder = CsvRecorder::new(writer).with_meta(meta);
+        self.steps.push(Box::new(RecordCsv(recorder)));
+        self
+    }
+
+    /// Finishes the pipeline, returning the stream of processed packets.
+    pub fn build(self) -> Processed<S> {
+        Processed {
+            stream: self.stream,
+            steps: self.steps,
+            ended: false,
+        }
+    }
+}
+
+/// Represents a packet stream with the steps of a `Pipeline` applied.
+pub struct Processed<S> {
+    /// The packet stream to process
+    stream: S,
+    /// Processing steps, in the order they are applied
+    steps: Vec<Box<dyn Step>>,
+    /// Whether the stream has ended or failed
+    ended: bool,
+}
+
+impl<S> Processed<S> {
+    /// Consumes the stream, returning the wrapped packet stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> Stream for Processed<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Packet, Error>;
+
+    /// Polls the next packet that passed all steps. Errors of the wrapped
+    /// stream or of a step end the stream after being yielded.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        'packets: while !this.ended {
+            let mut packet = match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(packet)) => packet,
+                Some(Err(e)) => {
+                    this.ended = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+                None => break,
+            };
+            for step in this.steps.iter_mut() {
+                match step.process(packet) {
+                    Ok(Some(processed)) => packet = processed,
+                    Ok(None) => continue 'packets,
+                    Err(e) => {
+                        this.ended = true;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+            }
+            return Poll::Ready(Some(Ok(packet)));
+        }
+        this.ended = true;
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, executor::block_on, stream};
+
+    use super::*;
+
+    #[test]
+    fn test_pipeline() {
+        let packets = [(10, 50), (0, 50), (0, 70)].map(|(poor_signal, attention)| {
+            Ok::<_, Error>(Packet {
+                poor_signal: Some(poor_signal),
+                attention: Some(attention),
+                ..Default::default()
+            })
+        });
+        let processed: Vec<_> = block_on(
+            Pipeline::from(stream::iter(packets))
+                .filter_signal(0)
+                .smooth_esense(0.5)
+                .build()
+                .collect(),
+        );
+        let attention: Vec<_> = processed
+            .into_iter()
+            .map(|p| p.unwrap().attention)
+            .collect();
+        assert_eq!(attention, vec![Some(50), Some(60)]);
+
+        // A dropout restarts the average
+        let packets = [(0, 40), (200, 0), (0, 80)].map(|(poor_signal, attention)| {
+            Ok::<_, Error>(Packet {
+                poor_signal: Some(poor_signal),
+                attention: Some(attention),
+                ..Default::default()
+            })
+        });
+        let processed: Vec<_> = block_on(
+            Pipeline::from(stream::iter(packets))
+                .smooth_esense(0.5)
+                .build()
+                .collect(),
+        );
+        let attention: Vec<_> = processed
+            .into_iter()
+            .map(|p| p.unwrap().attention)
+            .collect();
+        assert_eq!(attention, vec![Some(40), Some(0), Some(80)]);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_record_csv() {
+        let mut csv = RecordCsv(CsvRecorder::new(Vec::new()));
+        let packet = Packet {
+            poor_signal: Some(0),
+            raw_wave: Some(-5),
+            ..Default::default()
+        };
+        csv.process(packet.clone()).unwrap();
+        csv.process(packet).unwrap();
+        let csv = String::from_utf8(csv.0.finish().unwrap()).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.starts_with("timestamp,poor_signal,"));
+        assert_eq!(csv.lines().nth(2), Some(",0,,,,,-5,,,,,,,,,,"));
+    }
+}
@@ -0,0 +1,187 @@
+//! Provides functionality to tell which output mode a device is in, from a
+//! short burst of its data. It defines the `ProbeReport` struct, returned by
+//! `probe` for recorded bytes and by `probe_stream` for a live stream.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use cerebrust::{device::DeviceConfig, probe::probe_stream};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let mut stream = DeviceConfig::default().connect().await?;
+//!     let report = probe_stream(&mut stream, Duration::from_secs(3)).await?;
+//!     match report.output {
+//!         Some(output) => println!("Device is in {:?} mode", output),
+//!         None => println!("Could not tell the output mode: {:#?}", report),
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Output modes
+//!
+//! ThinkGear modules output either raw wave values at 512Hz in addition to
+//! the eSense values, which requires 57600 baud on a serial link (MyndBand
+//! default), or the eSense values and EEG power spectrum once per second
+//! only, which fits in 9600 baud (older MindSet firmware default). Over
+//! RFCOMM the baud rate does not matter, but the output mode tells which
+//! command and serial settings a device expects. The probe should cover at
+//! least two seconds, as the EEG power spectrum is reported once per second.
+
+use std::{
+    future::poll_fn,
+    io::Error,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    time::timeout,
+};
+
+use crate::{
+    comm::{SessionReport, analyze},
+    command::Command,
+};
+
+/// Raw wave packets per second from which the device is considered to be in
+/// raw output mode, half of the nominal 512Hz to tolerate losses.
+const RAW_RATE_THRESHOLD: f64 = 256.0;
+
+/// Represents what a probe observed on a stream, and the output mode it
+/// likely comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeReport {
+    /// Data quality report of the bytes read during the probe
+    pub report: SessionReport,
+    /// Duration of the probe
+    pub elapsed: Duration,
+    /// Bytes received per second
+    pub byte_rate: f64,
+    /// Raw wave packets received per second
+    pub raw_rate: f64,
+    /// Likely output mode, `Command::RawOutput` (57600 baud) or
+    /// `Command::NormalOutput` (9600 baud). `None` if no valid packet was
+    /// received, or if the packets match neither mode.
+    pub output: Option<Command>,
+}
+
+impl ProbeReport {
+    /// Returns the serial baud rate of the likely output mode, if known.
+    pub fn baud_rate(&self) -> Option<u32> {
+        match self.output? {
+            Command::RawOutput => Some(57600),
+            Command::NormalOutput => Some(9600),
+            _ => None,
+        }
+    }
+}
+
+/// Tells the likely output mode of a device from bytes it sent.
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes received from the device.
+/// * `elapsed` - The time it took to receive them.
+///
+/// # Returns
+///
+/// * `ProbeReport` - What was observed, and the likely output mode.
+pub fn probe(bytes: &[u8], elapsed: Duration) -> ProbeReport {
+    let report = analyze(bytes);
+    let secs = elapsed.as_secs_f64();
+    let rate = |count: usize| match secs {
+        0.0 => 0.0,
+        _ => count as f64 / secs,
+    };
+    let byte_rate = rate(bytes.len());
+    let raw_rate = rate(report.raw_wave_packets);
+    let output = if raw_rate >= RAW_RATE_THRESHOLD {
+        Some(Command::RawOutput)
+    } else if report.raw_wave_packets == 0 && report.eeg_power_packets > 0 {
+        Some(Command::NormalOutput)
+    } else {
+        None
+    };
+    ProbeReport {
+        report,
+        elapsed,
+        byte_rate,
+        raw_rate,
+        output,
+    }
+}
+
+/// Reads a stream for a while, and tells the likely output mode of the
+/// device sending it, see `probe`.
+///
+/// # Arguments
+///
+/// * `reader` - The stream to read, e.g. a freshly connected RFCOMM stream.
+/// * `duration` - How long to read for, preferably a few seconds.
+///
+/// # Returns
+///
+/// * `Result<ProbeReport, Error>` - What was observed, and the likely output mode.
+///
+/// # Errors
+///
+/// This function will return an error if reading from the stream fails.
+pub async fn probe_stream<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    duration: Duration,
+) -> Result<ProbeReport, Error> {
+    let start = Instant::now();
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 256];
+    loop {
+        let remaining = duration.saturating_sub(start.elapsed());
+        let mut buf = ReadBuf::new(&mut buffer);
+        let read = poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf));
+        match timeout(remaining, read).await {
+            Ok(result) => result?,
+            Err(_) => break,
+        }
+        if buf.filled().is_empty() {
+            // The stream has ended
+            break;
+        }
+        bytes.extend_from_slice(buf.filled());
+    }
+    Ok(probe(&bytes, start.elapsed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::frame;
+
+    #[test]
+    fn test_probe() {
+        let mut power = vec![0x02, 0x00, 0x04, 0x32, 0x05, 0x28, 0x83, 0x18];
+        power.extend([0; 24]);
+        let power = frame(&power);
+        let raw = frame(&[0x80, 0x02, 0x00, 0x10]);
+
+        let mut bytes = Vec::new();
+        for _ in 0..2 {
+            bytes.extend(raw.repeat(512));
+            bytes.extend(&power);
+        }
+        let report = probe(&bytes, Duration::from_secs(2));
+        assert_eq!(report.raw_rate, 512.0);
+        assert_eq!(report.output, Some(Command::RawOutput));
+        assert_eq!(report.baud_rate(), Some(57600));
+
+        let report = probe(&power.repeat(2), Duration::from_secs(2));
+        assert_eq!(report.output, Some(Command::NormalOutput));
+        assert_eq!(report.baud_rate(), Some(9600));
+
+        let report = probe(&[0x00; 64], Duration::from_secs(2));
+        assert_eq!(report.output, None);
+    }
+}
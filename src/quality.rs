@@ -0,0 +1,222 @@
+//! Provides functionality to interpret the signal quality reported by the
+//! NeuroSky device. It defines the `SignalQuality` enum which gives meaning to
+//! a single `poor_signal` value, the `ContactTracker` struct which smooths the
+//! `poor_signal` values over time, and the `ContactTrend` enum which tells
+//! whether the contact of the electrodes is improving or degrading.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{
+//!     comm::DataReader,
+//!     device::DeviceConfig,
+//!     quality::{ContactTracker, ContactTrend},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream);
+//!     let mut tracker = ContactTracker::new();
+//!     loop {
+//!         let packet = reader.poll_next().await?;
+//!         if let Some(poor_signal) = packet.poor_signal {
+//!             match tracker.update(poor_signal) {
+//!                 ContactTrend::Improving => println!("Good, keep adjusting..."),
+//!                 ContactTrend::Stable => println!("Hold there"),
+//!                 ContactTrend::Degrading => println!("Contact is getting worse"),
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! # Smoothing
+//!
+//! The `poor_signal` value fluctuates from one second to the next, so the
+//! tracker compares a fast and a slow exponential moving average of it. The
+//! trend is `Stable` while both averages are within the threshold of each
+//! other. A lower `poor_signal` means a better contact.
+
+/// Represents the meaning of a `poor_signal` value, see
+/// `Packet::signal_quality`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalQuality {
+    /// The electrodes have a good contact (0)
+    Good,
+    /// The signal is noisy, with the raw value (1 ~ 199); the higher, the
+    /// noisier
+    Poor(u8),
+    /// The electrodes are not touching the skin (200 and above)
+    NoContact,
+}
+
+impl SignalQuality {
+    /// Raw value the device reports when the electrodes are off the skin.
+    pub const NO_CONTACT: u8 = 200;
+}
+
+impl From<u8> for SignalQuality {
+    fn from(poor_signal: u8) -> Self {
+        match poor_signal {
+            0 => SignalQuality::Good,
+            SignalQuality::NO_CONTACT.. => SignalQuality::NoContact,
+            value => SignalQuality::Poor(value),
+        }
+    }
+}
+
+/// Represents the direction in which the contact quality is moving.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ContactTrend {
+    /// The signal quality is getting better
+    Improving,
+    /// The signal quality is not changing significantly
+    #[default]
+    Stable,
+    /// The signal quality is getting worse
+    Degrading,
+}
+
+/// Represents a tracker that estimates the contact quality trend from the
+/// `poor_signal` values of the packets.
+#[derive(Debug, Clone)]
+pub struct ContactTracker {
+    /// Weight of a new value in the fast moving average
+    alpha: f64,
+    /// Minimum difference between both averages to report a trend
+    threshold: f64,
+    /// Fast moving average, following recent values
+    fast: Option<f64>,
+    /// Slow moving average, following the long-term level
+    slow: Option<f64>,
+}
+
+impl Default for ContactTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContactTracker {
+    pub fn new() -> ContactTracker {
+        ContactTracker {
+            alpha: 0.5,
+            threshold: 5.0,
+            fast: None,
+            slow: None,
+        }
+    }
+
+    /// Updates the weight of a new value in the fast moving average. The slow
+    /// moving average uses a quarter of this weight. Default: 0.5.
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha` - The smoothing factor, in the range (0, 1].
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated tracker.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `alpha` is not in the range (0, 1].
+    pub fn with_smoothing(mut self, alpha: f64) -> Self {
+        assert!(
+            alpha > 0.0 && alpha <= 1.0,
+            "Smoothing factor must be in (0, 1]"
+        );
+        self.alpha = alpha;
+        self
+    }
+
+    /// Updates the minimum difference between the fast and slow moving
+    /// averages for a trend to be reported. Default: 5.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The minimum difference, in `poor_signal` units.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated tracker.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Feeds a new `poor_signal` value to the tracker.
+    ///
+    /// # Arguments
+    ///
+    /// * `poor_signal` - The signal quality reported by the device.
+    ///
+    /// # Returns
+    ///
+    /// * `ContactTrend` - The trend after taking the new value into account.
+    pub fn update(&mut self, poor_signal: u8) -> ContactTrend {
+        let value = poor_signal as f64;
+        let fast = self.fast.map_or(value, |x| x + self.alpha * (value - x));
+        let slow = self
+            .slow
+            .map_or(value, |x| x + self.alpha / 4.0 * (value - x));
+        self.fast = Some(fast);
+        self.slow = Some(slow);
+        self.trend()
+    }
+
+    /// Returns the current trend, `Stable` until a value has been fed.
+    pub fn trend(&self) -> ContactTrend {
+        match (self.fast, self.slow) {
+            (Some(fast), Some(slow)) if fast < slow - self.threshold => ContactTrend::Improving,
+            (Some(fast), Some(slow)) if fast > slow + self.threshold => ContactTrend::Degrading,
+            _ => ContactTrend::Stable,
+        }
+    }
+
+    /// Returns the smoothed `poor_signal` value, `None` until a value has
+    /// been fed.
+    pub fn smoothed(&self) -> Option<f64> {
+        self.fast
+    }
+
+    /// Forgets all values fed so far, e.g. after the headset was taken off.
+    pub fn reset(&mut self) {
+        self.fast = None;
+        self.slow = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contact_trend() {
+        let mut tracker = ContactTracker::new();
+        assert_eq!(tracker.trend(), ContactTrend::Stable);
+        for _ in 0..10 {
+            assert_eq!(tracker.update(200), ContactTrend::Stable);
+        }
+        assert_eq!(tracker.update(100), ContactTrend::Improving);
+        for _ in 0..50 {
+            tracker.update(100);
+        }
+        assert_eq!(tracker.trend(), ContactTrend::Stable);
+        assert_eq!(tracker.update(180), ContactTrend::Degrading);
+
+        tracker.reset();
+        assert_eq!(tracker.smoothed(), None);
+        assert_eq!(tracker.trend(), ContactTrend::Stable);
+    }
+
+    #[test]
+    fn test_signal_quality() {
+        assert_eq!(SignalQuality::from(0), SignalQuality::Good);
+        assert_eq!(SignalQuality::from(1), SignalQuality::Poor(1));
+        assert_eq!(SignalQuality::from(199), SignalQuality::Poor(199));
+        assert_eq!(SignalQuality::from(200), SignalQuality::NoContact);
+        assert_eq!(SignalQuality::from(255), SignalQuality::NoContact);
+    }
+}
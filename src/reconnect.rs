@@ -0,0 +1,311 @@
+//! Provides a packet reader that survives dropped connections. It defines the
+//! `ReconnectingReader` struct which owns a `DeviceConfig`, and connects
+//! again with exponential backoff whenever the connection is lost, the
+//! `Backoff` struct which sets the delays between attempts, and the
+//! `ReconnectEvent` enum which reports the attempts, e.g. to show a
+//! "reconnecting" state.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use cerebrust::{
+//!     device::DeviceConfig,
+//!     reconnect::{ReconnectEvent, ReconnectingReader},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let config = DeviceConfig::default().with_name("MyndBand".to_string());
+//!     let mut reader = ReconnectingReader::connect(config)
+//!         .await?
+//!         .with_reader(|reader| reader.with_warmup(Duration::from_secs(1)))
+//!         .on_event(|event| match event {
+//!             ReconnectEvent::Reconnecting { attempt, .. } => println!("Reconnecting ({attempt})"),
+//!             ReconnectEvent::Reconnected { .. } => println!("Reconnected"),
+//!             _ => {}
+//!         });
+//!     loop {
+//!         println!("{:?}", reader.poll_next().await?);
+//!     }
+//! }
+//! ```
+//!
+//! # Resynchronization
+//!
+//! Each connection gets a fresh `DataReader`, so bytes left over from the
+//! lost connection are discarded and the new stream is synchronized from its
+//! first sync bytes. Reader options are applied again through the closure
+//! given to `ReconnectingReader::with_reader`. Packets in flight when the
+//! connection dropped are lost.
+
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use bluer::rfcomm;
+use futures::Stream;
+use tokio::time::sleep;
+
+use crate::{
+    comm::{CommError, DataReader, Packet},
+    device::{self, DeviceConfig},
+};
+
+/// Represents the delays between reconnection attempts, doubling from
+/// `initial` up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    /// Delay before the first attempt. Default: 500ms.
+    pub initial: Duration,
+    /// Longest delay between two attempts. Default: 30s.
+    pub max: Duration,
+    /// Number of attempts before giving up, `None` to never give up.
+    /// Default: `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay before an attempt.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of the attempt, starting at 1.
+    ///
+    /// # Returns
+    ///
+    /// * `Duration` - The delay, `initial` doubled for each attempt before,
+    ///   at most `max`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// Represents a change of the connection of a `ReconnectingReader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconnectEvent {
+    /// The connection was lost, with the kind of the error that ended it
+    Disconnected { kind: ErrorKind },
+    /// An attempt to connect again starts after `delay`
+    Reconnecting { attempt: u32, delay: Duration },
+    /// The attempt succeeded, packets are read from the new connection
+    Reconnected { attempt: u32 },
+}
+
+/// Closure applying options to the reader of each connection.
+type Configure = Box<dyn FnMut(DataReader) -> DataReader + Send>;
+
+/// Represents a reader of packets that connects to the device again whenever
+/// the connection is lost.
+pub struct ReconnectingReader {
+    /// Configuration the device is connected with
+    config: DeviceConfig,
+    /// Reader of the current connection, `None` while disconnected
+    reader: Option<DataReader>,
+    /// Delays between reconnection attempts
+    backoff: Backoff,
+    /// Options applied to the reader of each connection
+    configure: Option<Configure>,
+    /// Closure invoked on connection changes
+    on_event: Option<Box<dyn FnMut(ReconnectEvent) + Send>>,
+}
+
+impl ReconnectingReader {
+    /// Connects to the device a first time. Unlike later reconnections, the
+    /// first connection is attempted once, so a wrong configuration fails
+    /// right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration to connect, and reconnect, with.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ReconnectingReader, device::Error>` - The connected reader.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the first connection fails, see
+    /// `DeviceConfig::connect`.
+    pub async fn connect(config: DeviceConfig) -> device::Result<ReconnectingReader> {
+        let stream = config.connect().await?;
+        Ok(ReconnectingReader {
+            config,
+            reader: Some(DataReader::new(stream)),
+            backoff: Backoff::default(),
+            configure: None,
+            on_event: None,
+        })
+    }
+
+    /// Updates the delays between reconnection attempts. Default: see
+    /// `Backoff`.
+    ///
+    /// # Arguments
+    ///
+    /// * `backoff` - The delays between attempts.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Applies options to the reader of each connection, e.g. a warmup
+    /// period or callbacks. The closure runs for the current connection
+    /// right away, and for each new connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - The closure, given a fresh reader and returning the
+    ///   configured reader.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn with_reader(
+        mut self,
+        mut configure: impl FnMut(DataReader) -> DataReader + Send + 'static,
+    ) -> Self {
+        self.reader = self.reader.map(&mut configure);
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    /// Registers a closure invoked when the connection is lost, before each
+    /// reconnection attempt, and once reconnected. Replaces any closure
+    /// registered before.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - The closure, given the event.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated reader.
+    pub fn on_event(mut self, cb: impl FnMut(ReconnectEvent) + Send + 'static) -> Self {
+        self.on_event = Some(Box::new(cb));
+        self
+    }
+
+    /// Returns the reader of the current connection, `None` while
+    /// disconnected, e.g. to look at its snapshot.
+    pub fn reader(&self) -> Option<&DataReader> {
+        self.reader.as_ref()
+    }
+
+    /// Reads the next data packet, connecting again first if the connection
+    /// was lost. Read errors and the end of the stream trigger a
+    /// reconnection; corrupted frames returned with
+    /// `DataReader::with_frame_errors` and `InvalidData` errors, e.g. from
+    /// `DataReader::with_unknown_code_limit`, are returned as is.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a packet cannot be read for a
+    /// reason a reconnection does not fix, or if all attempts of the backoff
+    /// failed, in which case the error of the last attempt is returned.
+    pub async fn poll_next(&mut self) -> Result<Packet, CommError> {
+        loop {
+            if let Some(reader) = &mut self.reader {
+                let kind = match reader.poll_next().await {
+                    Ok(packet) => return Ok(packet),
+                    Err(CommError::Io(e)) if e.kind() != ErrorKind::InvalidData => e.kind(),
+                    Err(CommError::TruncatedPayload) => ErrorKind::UnexpectedEof,
+                    Err(e) => return Err(e),
+                };
+                // Drop the reader first, which closes the connection
+                self.reader = None;
+                self.emit(ReconnectEvent::Disconnected { kind });
+            }
+            self.reconnect().await?;
+        }
+    }
+
+    /// Consumes the reader, returning a stream of the packets read across
+    /// connections, following the same contract as `poll_next`. The stream
+    /// ends after yielding an error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Packet, CommError>> + Send {
+        futures::stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+            match this.poll_next().await {
+                Ok(packet) => Some((Ok(packet), Some(this))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Connects again, waiting according to the backoff between attempts.
+    async fn reconnect(&mut self) -> Result<(), CommError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let delay = self.backoff.delay(attempt);
+            self.emit(ReconnectEvent::Reconnecting { attempt, delay });
+            sleep(delay).await;
+            match self.config.connect().await {
+                Ok(stream) => {
+                    self.reader = Some(self.new_reader(stream));
+                    self.emit(ReconnectEvent::Reconnected { attempt });
+                    return Ok(());
+                }
+                Err(e) if self.backoff.max_attempts.is_some_and(|max| attempt >= max) => {
+                    return Err(Error::from(e).into());
+                }
+                Err(e) => diagnostic!(warn, "Reconnection attempt {} failed: {}", attempt, e),
+            }
+        }
+    }
+
+    /// Wraps a fresh stream into a reader with the configured options.
+    fn new_reader(&mut self, stream: rfcomm::Stream) -> DataReader {
+        let reader = DataReader::new(stream);
+        match &mut self.configure {
+            Some(configure) => configure(reader),
+            None => reader,
+        }
+    }
+
+    fn emit(&mut self, event: ReconnectEvent) {
+        if let Some(cb) = &mut self.on_event {
+            cb(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(3),
+            max_attempts: None,
+        };
+        let delays: Vec<_> = (1..=5).map(|attempt| backoff.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000].map(Duration::from_millis)
+        );
+        assert_eq!(backoff.delay(100), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,331 @@
+//! Provides an adapter to smooth the eSense values, which are jittery from
+//! one second to the next. It defines the `Smoother` struct which replaces
+//! the attention and meditation values of each packet with a moving average
+//! of the last ones, either exponential or over a window of readings.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig, smooth::Smoother};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut packets = Smoother::new(DataReader::new(stream)).with_window(5);
+//!     while let Some(packet) = packets.next().await {
+//!         if let Some(attention) = packet?.attention {
+//!             println!("Attention: {}", attention);
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! # Restarts
+//!
+//! Averaging across an interruption would blend unrelated readings, so the
+//! average starts over from the next reading after:
+//!
+//! - a packet whose signal quality is worse than the threshold, whose
+//!   eSense values are passed through unchanged, as they are meaningless
+//!   while the contact is poor, or
+//! - a gap between two readings longer than the maximum gap, measured with
+//!   `Packet::received_at`, so only packets read by a `DataReader` are
+//!   checked.
+//!
+//! Packets are never dropped or reordered, and the adapter follows the same
+//! contract as the `Stream` implementation of `DataReader`.
+
+use std::{
+    collections::VecDeque,
+    io::Error,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+use futures::Stream;
+
+use crate::comm::Packet;
+
+/// Kind of moving average taken over the readings.
+#[derive(Debug, Clone, Copy)]
+enum Average {
+    /// Exponential moving average, with the weight of a new reading
+    Exponential(f64),
+    /// Mean of the last readings, with the number of readings
+    Window(usize),
+}
+
+/// Moving average of a single eSense value.
+#[derive(Debug, Default)]
+struct Series {
+    /// Current exponential moving average
+    average: Option<f64>,
+    /// Last readings, oldest first
+    readings: VecDeque<u8>,
+}
+
+impl Series {
+    /// Feeds a reading, returning the smoothed value.
+    fn push(&mut self, average: Average, value: u8) -> u8 {
+        let smoothed = match average {
+            Average::Exponential(alpha) => {
+                let value = value as f64;
+                let smoothed = self.average.map_or(value, |x| x + alpha * (value - x));
+                self.average = Some(smoothed);
+                smoothed
+            }
+            Average::Window(size) => {
+                if self.readings.len() == size {
+                    self.readings.pop_front();
+                }
+                self.readings.push_back(value);
+                let sum: u32 = self.readings.iter().map(|&x| x as u32).sum();
+                sum as f64 / self.readings.len() as f64
+            }
+        };
+        smoothed.round() as u8
+    }
+
+    fn reset(&mut self) {
+        self.average = None;
+        self.readings.clear();
+    }
+}
+
+/// Moving averages of the eSense values, restarted after a dropout or a
+/// gap. Shared by `Smoother` and `Pipeline::smooth_esense`.
+#[derive(Debug)]
+pub(crate) struct Averages {
+    /// Kind of moving average
+    average: Average,
+    /// Worst signal quality for which the values are smoothed
+    max_poor_signal: u8,
+    /// Longest interval between two readings averaged together
+    max_gap: Duration,
+    /// Moving average of the attention values
+    attention: Series,
+    /// Moving average of the meditation values
+    meditation: Series,
+    /// Instant the last reading was received at, if known
+    last: Option<Instant>,
+}
+
+impl Averages {
+    /// Creates exponential moving averages with the weight of a new reading,
+    /// and the default dropout and gap thresholds.
+    pub(crate) fn exponential(alpha: f64) -> Averages {
+        assert!(
+            alpha > 0.0 && alpha <= 1.0,
+            "Smoothing factor must be in (0, 1]"
+        );
+        Averages {
+            average: Average::Exponential(alpha),
+            max_poor_signal: 50,
+            max_gap: Duration::from_secs(3),
+            attention: Series::default(),
+            meditation: Series::default(),
+            last: None,
+        }
+    }
+
+    /// Replaces the eSense values of a packet with their moving averages,
+    /// restarting them after a dropout or a gap.
+    pub(crate) fn smooth(&mut self, packet: &mut Packet) {
+        if packet.attention.is_none() && packet.meditation.is_none() {
+            return;
+        }
+        let dropout = packet.poor_signal.is_some_and(|x| x > self.max_poor_signal);
+        let gap = match (self.last, packet.received_at) {
+            (Some(last), Some(now)) => now.saturating_duration_since(last) > self.max_gap,
+            _ => false,
+        };
+        if dropout || gap {
+            self.attention.reset();
+            self.meditation.reset();
+        }
+        self.last = packet.received_at;
+        if dropout {
+            return;
+        }
+        let average = self.average;
+        packet.attention = packet.attention.map(|x| self.attention.push(average, x));
+        packet.meditation = packet.meditation.map(|x| self.meditation.push(average, x));
+    }
+}
+
+/// Represents an adapter that replaces the attention and meditation values
+/// of each packet with a moving average of the last readings.
+pub struct Smoother<S> {
+    /// The packet stream to smooth
+    stream: S,
+    /// Moving averages of the eSense values
+    averages: Averages,
+}
+
+impl<S> Smoother<S> {
+    pub fn new(stream: S) -> Smoother<S> {
+        Smoother {
+            stream,
+            averages: Averages::exponential(0.3),
+        }
+    }
+
+    /// Takes an exponential moving average of the readings. This is the
+    /// default, with an `alpha` of 0.3.
+    ///
+    /// # Arguments
+    ///
+    /// * `alpha` - The weight of a new reading, in the range (0, 1]. The
+    ///   lower, the smoother.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `alpha` is not in the range (0, 1].
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.averages.average = Averages::exponential(alpha).average;
+        self
+    }
+
+    /// Takes the mean of the last readings instead of an exponential moving
+    /// average. Until the window is full, the mean is taken over the readings
+    /// so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The number of readings, i.e. seconds. Must be non-zero.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `window` is zero.
+    pub fn with_window(mut self, window: usize) -> Self {
+        assert!(window > 0, "Window must be non-zero");
+        self.averages.average = Average::Window(window);
+        self
+    }
+
+    /// Updates the worst signal quality for which the values are smoothed.
+    /// Worse readings restart the average. Default: 50.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_poor_signal` - The worst signal quality (0 ~ 255), 0 meaning a
+    ///   perfect signal and 200 no contact. Values equal to it are smoothed.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    pub fn with_max_poor_signal(mut self, max_poor_signal: u8) -> Self {
+        self.averages.max_poor_signal = max_poor_signal;
+        self
+    }
+
+    /// Updates the longest interval between two readings averaged together.
+    /// A longer gap, e.g. after a dropped connection, restarts the average.
+    /// Default: 3s, i.e. two missed readings.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_gap` - The longest interval between two readings.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated adapter.
+    pub fn with_max_gap(mut self, max_gap: Duration) -> Self {
+        self.averages.max_gap = max_gap;
+        self
+    }
+
+    /// Consumes the adapter, returning the wrapped packet stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, E> Stream for Smoother<S>
+where
+    S: Stream<Item = Result<Packet, E>> + Unpin,
+    E: Into<Error>,
+{
+    type Item = Result<Packet, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = ready!(Pin::new(&mut this.stream).poll_next(cx));
+        Poll::Ready(item.map(|item| {
+            item.map_err(Into::into).map(|mut packet| {
+                this.averages.smooth(&mut packet);
+                packet
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, executor::block_on, stream};
+
+    use super::*;
+
+    #[test]
+    fn test_smoother() {
+        let start = Instant::now();
+        let esense = |secs, poor_signal, attention| Packet {
+            poor_signal: Some(poor_signal),
+            attention: Some(attention),
+            meditation: Some(100 - attention),
+            received_at: Some(start + Duration::from_secs(secs)),
+            ..Default::default()
+        };
+        let raw = Packet {
+            raw_wave: Some(7),
+            ..Default::default()
+        };
+        let packets = [
+            esense(0, 0, 40),
+            raw,
+            esense(1, 0, 60),
+            esense(2, 0, 80),
+            // Dropout, passed through and restarting the average
+            esense(3, 200, 0),
+            esense(4, 0, 20),
+            esense(5, 0, 40),
+            // Gap, restarting the average
+            esense(10, 0, 90),
+        ];
+        let smooth = |smoother: Smoother<_>| -> Vec<Packet> {
+            block_on(smoother.map(Result::unwrap).collect())
+        };
+
+        let packets = || stream::iter(packets.clone().map(Ok::<_, Error>));
+        let smoothed = smooth(Smoother::new(packets()).with_window(2));
+        let attention: Vec<_> = smoothed.iter().map(|p| p.attention).collect();
+        assert_eq!(
+            attention,
+            vec![
+                Some(40),
+                None,
+                Some(50),
+                Some(70),
+                Some(0),
+                Some(20),
+                Some(30),
+                Some(90)
+            ]
+        );
+        assert_eq!(smoothed[3].meditation, Some(30));
+        let smoothed = smooth(Smoother::new(packets()).with_alpha(0.5));
+        let attention: Vec<_> = smoothed[..4].iter().map(|p| p.attention).collect();
+        assert_eq!(attention, vec![Some(40), None, Some(50), Some(65)]);
+    }
+}
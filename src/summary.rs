@@ -0,0 +1,244 @@
+//! Provides functionality to summarize a session once it is over. It defines
+//! the `SessionStats` struct which accumulates statistics as packets are
+//! received, and the `SessionSummary` struct it produces on demand, so every
+//! application reports sessions with the same definitions.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use cerebrust::{comm::DataReader, device::DeviceConfig};
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let stream = DeviceConfig::default().connect().await?;
+//!     let mut reader = DataReader::new(stream);
+//!     for _ in 0..512 * 60 {
+//!         reader.poll_next().await?;
+//!     }
+//!     // `DataReader` feeds its own `SessionStats`
+//!     println!("{:#?}", reader.summary());
+//!     Ok(())
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::comm::Packet;
+
+/// Represents the mean, minimum and maximum of a value over a session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueStats {
+    /// Mean value
+    pub mean: f64,
+    /// Minimum value
+    pub min: u8,
+    /// Maximum value
+    pub max: u8,
+}
+
+/// Represents the summary of a session, as returned by `SessionStats::summary`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionSummary {
+    /// Time elapsed since the start of the session
+    pub duration: Duration,
+    /// Number of valid packets
+    pub total_packets: u64,
+    /// Number of frames dropped due to a checksum mismatch
+    pub checksum_failures: u64,
+    /// Fraction of frames dropped due to a checksum mismatch (0 ~ 1)
+    pub checksum_error_rate: f64,
+    /// Attention eSense statistics, `None` if never received
+    pub attention: Option<ValueStats>,
+    /// Meditation eSense statistics, `None` if never received
+    pub meditation: Option<ValueStats>,
+    /// Mean EEG power of each band (uV^2), in the order of `Band::ALL`.
+    /// `None` if never received.
+    pub mean_power: Option<[f64; 8]>,
+    /// Fraction of the signal quality reports that were good (0 ~ 1), `None`
+    /// if never received
+    pub good_signal_ratio: Option<f64>,
+}
+
+/// Running statistics of a single value.
+#[derive(Debug, Default, Clone, Copy)]
+struct Running {
+    count: u64,
+    sum: u64,
+    min: u8,
+    max: u8,
+}
+
+impl Running {
+    fn push(&mut self, value: u8) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value as u64;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn stats(&self) -> Option<ValueStats> {
+        (self.count > 0).then(|| ValueStats {
+            mean: self.sum as f64 / self.count as f64,
+            min: self.min,
+            max: self.max,
+        })
+    }
+}
+
+/// Represents an accumulator of session statistics, fed with every packet
+/// received during the session.
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    /// Start of the session
+    started: Instant,
+    /// Worst signal quality counted as good
+    good_signal: u8,
+    total_packets: u64,
+    checksum_failures: u64,
+    attention: Running,
+    meditation: Running,
+    /// Number of EEG power packets, and the sum of each band
+    power_count: u64,
+    power_sum: [u64; 8],
+    /// Number of signal quality reports, and how many were good
+    signal_count: u64,
+    good_signal_count: u64,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionStats {
+    /// Starts accumulating statistics of a session starting now.
+    pub fn new() -> SessionStats {
+        SessionStats {
+            started: Instant::now(),
+            good_signal: 0,
+            total_packets: 0,
+            checksum_failures: 0,
+            attention: Running::default(),
+            meditation: Running::default(),
+            power_count: 0,
+            power_sum: [0; 8],
+            signal_count: 0,
+            good_signal_count: 0,
+        }
+    }
+
+    /// Updates the worst signal quality counted as good. Default: 0, i.e.
+    /// only a perfect signal.
+    ///
+    /// # Arguments
+    ///
+    /// * `poor_signal` - The worst signal quality counted as good (0 ~ 255).
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The updated accumulator.
+    pub fn with_good_signal(mut self, poor_signal: u8) -> Self {
+        self.good_signal = poor_signal;
+        self
+    }
+
+    /// Feeds a valid packet to the accumulator.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The packet received.
+    pub fn push(&mut self, packet: &Packet) {
+        self.total_packets += 1;
+        if let Some(attention) = packet.attention {
+            self.attention.push(attention);
+        }
+        if let Some(meditation) = packet.meditation {
+            self.meditation.push(meditation);
+        }
+        if let Some(power) = packet.eeg_power {
+            self.power_count += 1;
+            for (sum, value) in self.power_sum.iter_mut().zip(power.as_array()) {
+                *sum += value as u64;
+            }
+        }
+        if let Some(poor_signal) = packet.poor_signal {
+            self.signal_count += 1;
+            if poor_signal <= self.good_signal {
+                self.good_signal_count += 1;
+            }
+        }
+    }
+
+    /// Counts a frame dropped due to a checksum mismatch.
+    pub fn push_checksum_failure(&mut self) {
+        self.checksum_failures += 1;
+    }
+
+    /// Returns the summary of the session so far.
+    pub fn summary(&self) -> SessionSummary {
+        let frames = self.total_packets + self.checksum_failures;
+        SessionSummary {
+            duration: self.started.elapsed(),
+            total_packets: self.total_packets,
+            checksum_failures: self.checksum_failures,
+            checksum_error_rate: match frames {
+                0 => 0.0,
+                _ => self.checksum_failures as f64 / frames as f64,
+            },
+            attention: self.attention.stats(),
+            meditation: self.meditation.stats(),
+            mean_power: (self.power_count > 0)
+                .then(|| self.power_sum.map(|x| x as f64 / self.power_count as f64)),
+            good_signal_ratio: (self.signal_count > 0)
+                .then(|| self.good_signal_count as f64 / self.signal_count as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::Power;
+
+    #[test]
+    fn test_session_summary() {
+        let mut stats = SessionStats::new().with_good_signal(50);
+        assert_eq!(stats.summary().attention, None);
+        for (poor_signal, attention, delta) in [(0, 40, 10), (100, 60, 30)] {
+            stats.push(&Packet {
+                poor_signal: Some(poor_signal),
+                attention: Some(attention),
+                eeg_power: Some(Power {
+                    delta,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+        stats.push(&Packet {
+            raw_wave: Some(0),
+            ..Default::default()
+        });
+        stats.push_checksum_failure();
+
+        let summary = stats.summary();
+        assert_eq!(summary.total_packets, 3);
+        assert_eq!(summary.checksum_error_rate, 0.25);
+        assert_eq!(
+            summary.attention,
+            Some(ValueStats {
+                mean: 50.0,
+                min: 40,
+                max: 60
+            })
+        );
+        assert_eq!(summary.meditation, None);
+        assert_eq!(summary.mean_power.unwrap()[0], 20.0);
+        assert_eq!(summary.good_signal_ratio, Some(0.5));
+    }
+}